@@ -0,0 +1,478 @@
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use bitcoincore_rpc_json::bitcoin::{BlockHash, Transaction, Txid};
+
+use crate::{
+    chain::{ChainEvent, ChainFollower},
+    client::{Client, Result},
+    error::Error,
+    jsonrpc::JsonRpcError,
+};
+
+/// Confirmations after which a transaction is considered settled by default.
+pub const DEFAULT_CONFIRMATIONS: u32 = 6;
+
+/// RPC error code for transactions rejected by mempool policy or consensus.
+const RPC_VERIFY_REJECTED: i64 = -26;
+/// RPC error code for transactions already in the chain.
+const RPC_VERIFY_ALREADY_IN_CHAIN: i64 = -27;
+/// RPC error code for unknown transactions, among others.
+const RPC_INVALID_ADDRESS_OR_KEY: i64 = -5;
+
+/// Why the node refused a transaction.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum RejectReason {
+    /// The transaction is already in the mempool.
+    AlreadyInMempool,
+    /// The transaction is already confirmed.
+    AlreadyInChain,
+    /// An input does not exist or is already spent.
+    MissingInputs,
+    /// An input is spent by another mempool transaction that cannot be
+    /// replaced.
+    MempoolConflict,
+    /// The fee is too low for the mempool, or for replacing a conflict.
+    InsufficientFee(String),
+    /// The fee exceeds the maximum accepted by `sendrawtransaction`.
+    MaxFeeExceeded,
+    /// An output is below the dust threshold.
+    Dust,
+    /// The transaction is not final yet because of its locktime.
+    NonFinal,
+    /// The transaction would exceed the mempool ancestor or descendant limits.
+    TooLongMempoolChain,
+    /// The transaction is valid but violates standardness policy.
+    NonStandard(String),
+    /// The transaction is invalid.
+    Invalid(String),
+}
+
+impl RejectReason {
+    /// Classifies a reject reason as reported by `testmempoolaccept` or in the
+    /// message of a `sendrawtransaction` error.
+    pub fn from_reject_reason(reason: &str) -> Self {
+        let reason = reason.trim();
+        let lower = reason.to_ascii_lowercase();
+        let has = |s: &str| lower.contains(s);
+        if has("txn-already-in-mempool") || has("txn-already-known") {
+            RejectReason::AlreadyInMempool
+        } else if has("already in block chain") || has("already in utxo set") {
+            RejectReason::AlreadyInChain
+        } else if has("missingorspent") || has("missing inputs") || has("missing-inputs") {
+            RejectReason::MissingInputs
+        } else if has("txn-mempool-conflict") {
+            RejectReason::MempoolConflict
+        } else if has("fee exceeds maximum") || has("max-fee-exceeded") {
+            RejectReason::MaxFeeExceeded
+        } else if has("insufficient fee")
+            || has("min relay fee not met")
+            || has("mempool min fee not met")
+            || has("mempool full")
+        {
+            RejectReason::InsufficientFee(reason.to_string())
+        } else if lower == "dust" {
+            RejectReason::Dust
+        } else if has("non-final") || has("non-bip68-final") {
+            RejectReason::NonFinal
+        } else if has("too-long-mempool-chain") {
+            RejectReason::TooLongMempoolChain
+        } else if has("mandatory-script-verify-flag-failed") || has("bad-txns") {
+            RejectReason::Invalid(reason.to_string())
+        } else {
+            RejectReason::NonStandard(reason.to_string())
+        }
+    }
+
+    /// Classifies an error returned by `sendrawtransaction`.
+    pub fn from_rpc_error(err: &JsonRpcError) -> Self {
+        match err.code {
+            RPC_VERIFY_ALREADY_IN_CHAIN => RejectReason::AlreadyInChain,
+            RPC_VERIFY_REJECTED => RejectReason::from_reject_reason(&err.message),
+            _ => match RejectReason::from_reject_reason(&err.message) {
+                RejectReason::NonStandard(msg) => RejectReason::Invalid(msg),
+                reason => reason,
+            },
+        }
+    }
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectReason::AlreadyInMempool => write!(f, "already in mempool"),
+            RejectReason::AlreadyInChain => write!(f, "already in chain"),
+            RejectReason::MissingInputs => write!(f, "missing or spent inputs"),
+            RejectReason::MempoolConflict => write!(f, "conflicts with a mempool transaction"),
+            RejectReason::InsufficientFee(msg) => write!(f, "insufficient fee: {msg}"),
+            RejectReason::MaxFeeExceeded => write!(f, "fee exceeds maximum"),
+            RejectReason::Dust => write!(f, "dust output"),
+            RejectReason::NonFinal => write!(f, "non-final"),
+            RejectReason::TooLongMempoolChain => write!(f, "too long mempool chain"),
+            RejectReason::NonStandard(msg) => write!(f, "non-standard: {msg}"),
+            RejectReason::Invalid(msg) => write!(f, "invalid: {msg}"),
+        }
+    }
+}
+
+/// Something that happened to a tracked transaction.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TxEvent {
+    /// The transaction is in the mempool (0 confirmations).
+    Accepted { txid: Txid },
+    /// The transaction had dropped from the mempool and was broadcast again.
+    Rebroadcast { txid: Txid },
+    /// The transaction was first seen confirmed.
+    Confirmed {
+        txid: Txid,
+        block_hash: BlockHash,
+        height: u64,
+        confirmations: u32,
+    },
+    /// The transaction reached the configured number of confirmations and is
+    /// no longer tracked.
+    Settled {
+        txid: Txid,
+        block_hash: BlockHash,
+        height: u64,
+    },
+    /// The block confirming the transaction was disconnected by a reorg.
+    Reorged { txid: Txid, block_hash: BlockHash },
+    /// Another mempool transaction spending the same inputs replaced it.
+    Replaced { txid: Txid, by: Txid },
+    /// An input was spent in the chain by another transaction.
+    Conflicted { txid: Txid },
+    /// The node refused to take the transaction back after it dropped.
+    Rejected { txid: Txid, reason: RejectReason },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum TxState {
+    Unconfirmed {
+        in_mempool: bool,
+    },
+    Confirmed {
+        block_hash: BlockHash,
+        height: u64,
+        announced: bool,
+    },
+}
+
+struct Tracked {
+    tx: Transaction,
+    state: TxState,
+}
+
+/// Broadcasts transactions and follows them until they are buried.
+///
+/// Call [BroadcastTracker::poll] periodically, or whenever a new block
+/// arrives, to collect [TxEvent]s. Confirmations are found by following the
+/// best chain block by block, so the node needs no `-txindex`.
+pub struct BroadcastTracker {
+    client: Arc<Client>,
+    test_accept: bool,
+    confirmations: u32,
+    follower: ChainFollower,
+    txs: HashMap<Txid, Tracked>,
+}
+
+impl BroadcastTracker {
+    /// Creates a tracker following the chain from the node's current tip.
+    pub async fn new(client: Arc<Client>) -> Result<Self> {
+        let follower = ChainFollower::from_tip(&client).await?;
+        Ok(Self {
+            client,
+            test_accept: false,
+            confirmations: DEFAULT_CONFIRMATIONS,
+            follower,
+            txs: HashMap::new(),
+        })
+    }
+
+    /// Checks transactions with `testmempoolaccept` before submitting them.
+    pub fn test_accept(mut self, test_accept: bool) -> Self {
+        self.test_accept = test_accept;
+        self
+    }
+
+    /// Sets the number of confirmations after which a transaction is settled.
+    pub fn confirmations(mut self, confirmations: u32) -> Self {
+        self.confirmations = confirmations.max(1);
+        self
+    }
+
+    /// Txids of the transactions still being tracked.
+    pub fn tracked(&self) -> impl Iterator<Item = &Txid> {
+        self.txs.keys()
+    }
+
+    /// Broadcasts `tx` with `sendrawtransaction` and starts tracking it.
+    ///
+    /// Fails with [Error::Rejected] if the node refuses the transaction. A
+    /// transaction the node already knows about is tracked as usual.
+    pub async fn submit(&mut self, tx: Transaction) -> Result<Txid> {
+        let txid = tx.compute_txid();
+        if self.test_accept {
+            let res = self.client.test_mempool_accept(&[&tx]).await?;
+            if let Some(res) = res.into_iter().find(|r| !r.allowed) {
+                let reason = RejectReason::from_reject_reason(
+                    res.reject_reason.as_deref().unwrap_or_default(),
+                );
+                if reason != RejectReason::AlreadyInMempool {
+                    return Err(Error::Rejected { txid, reason });
+                }
+            }
+        }
+
+        match self.client.send_raw_transaction(&tx).await {
+            Ok(_) => {}
+            Err(Error::JsonRpcError(err)) => match RejectReason::from_rpc_error(&err) {
+                RejectReason::AlreadyInMempool | RejectReason::AlreadyInChain => {}
+                reason => return Err(Error::Rejected { txid, reason }),
+            },
+            Err(err) => return Err(err),
+        }
+        self.track(tx);
+        Ok(txid)
+    }
+
+    /// Tracks a transaction that was broadcast by other means.
+    pub fn track(&mut self, tx: Transaction) {
+        self.txs.insert(
+            tx.compute_txid(),
+            Tracked {
+                tx,
+                state: TxState::Unconfirmed { in_mempool: false },
+            },
+        );
+    }
+
+    /// Stops tracking `txid`.
+    pub fn untrack(&mut self, txid: &Txid) -> Option<Transaction> {
+        self.txs.remove(txid).map(|t| t.tx)
+    }
+
+    /// Catches up with the chain and the mempool and returns what happened
+    /// to the tracked transactions since the last poll.
+    pub async fn poll(&mut self) -> Result<Vec<TxEvent>> {
+        let mut events = Vec::new();
+
+        for event in self.follower.poll(&self.client).await? {
+            match event {
+                ChainEvent::Disconnected { hash, .. } => {
+                    for (txid, tracked) in self.txs.iter_mut() {
+                        if matches!(tracked.state, TxState::Confirmed { block_hash, .. } if block_hash == hash)
+                        {
+                            tracked.state = TxState::Unconfirmed { in_mempool: false };
+                            events.push(TxEvent::Reorged {
+                                txid: *txid,
+                                block_hash: hash,
+                            });
+                        }
+                    }
+                }
+                ChainEvent::Connected { height, hash } => {
+                    if !self
+                        .txs
+                        .values()
+                        .any(|t| matches!(t.state, TxState::Unconfirmed { .. }))
+                    {
+                        continue;
+                    }
+                    let block = self.client.get_block_info(&hash).await?;
+                    for txid in block.tx {
+                        if let Some(tracked) = self.txs.get_mut(&txid) {
+                            tracked.state = TxState::Confirmed {
+                                block_hash: hash,
+                                height,
+                                announced: false,
+                            };
+                        }
+                    }
+                }
+            }
+        }
+
+        let (tip_height, _) = self.follower.tip();
+        let txids: Vec<Txid> = self.txs.keys().copied().collect();
+        for txid in txids {
+            let state = self.txs[&txid].state;
+            match state {
+                TxState::Confirmed {
+                    block_hash,
+                    height,
+                    announced,
+                } => {
+                    let confirmations = (tip_height + 1).saturating_sub(height) as u32;
+                    if !announced {
+                        events.push(TxEvent::Confirmed {
+                            txid,
+                            block_hash,
+                            height,
+                            confirmations,
+                        });
+                        if let Some(tracked) = self.txs.get_mut(&txid) {
+                            tracked.state = TxState::Confirmed {
+                                block_hash,
+                                height,
+                                announced: true,
+                            };
+                        }
+                    }
+                    if confirmations >= self.confirmations {
+                        self.txs.remove(&txid);
+                        events.push(TxEvent::Settled {
+                            txid,
+                            block_hash,
+                            height,
+                        });
+                    }
+                }
+                TxState::Unconfirmed { in_mempool } => {
+                    if let Some(event) = self.check_unconfirmed(&txid, in_mempool).await? {
+                        events.push(event);
+                    }
+                }
+            }
+        }
+        Ok(events)
+    }
+
+    /// Works out what became of a transaction not seen in a block.
+    async fn check_unconfirmed(
+        &mut self,
+        txid: &Txid,
+        was_in_mempool: bool,
+    ) -> Result<Option<TxEvent>> {
+        match self.client.get_mempool_entry(txid).await {
+            Ok(_) => {
+                self.set_state(txid, TxState::Unconfirmed { in_mempool: true });
+                return Ok((!was_in_mempool).then_some(TxEvent::Accepted { txid: *txid }));
+            }
+            Err(Error::JsonRpcError(err)) if err.code == RPC_INVALID_ADDRESS_OR_KEY => {}
+            Err(err) => return Err(err),
+        }
+        self.set_state(txid, TxState::Unconfirmed { in_mempool: false });
+
+        // It may have confirmed before the tracker started following the
+        // chain; this only succeeds with -txindex or for wallet transactions.
+        if let Ok(info) = self.client.get_raw_transaction_info(txid, None).await {
+            if let Some(block_hash) = info.blockhash {
+                let header = self.client.get_block_header_info(&block_hash).await?;
+                if header.confirmations > 0 {
+                    self.set_state(
+                        txid,
+                        TxState::Confirmed {
+                            block_hash,
+                            height: header.height as u64,
+                            announced: false,
+                        },
+                    );
+                    return Ok(None);
+                }
+            }
+        }
+
+        let tx = self.txs[txid].tx.clone();
+        let prevouts: Vec<_> = tx.input.iter().map(|i| i.previous_output).collect();
+        // Requires Core v24; older nodes fall through to the UTXO check.
+        if let Ok(spends) = self.client.get_tx_spending_prevout(&prevouts).await {
+            if let Some(by) = spends
+                .into_iter()
+                .filter_map(|s| s.spending_txid)
+                .find(|s| s != txid)
+            {
+                self.txs.remove(txid);
+                return Ok(Some(TxEvent::Replaced { txid: *txid, by }));
+            }
+        }
+        for prevout in &prevouts {
+            if self
+                .client
+                .get_tx_out(&prevout.txid, prevout.vout, Some(true))
+                .await?
+                .is_none()
+            {
+                self.txs.remove(txid);
+                return Ok(Some(TxEvent::Conflicted { txid: *txid }));
+            }
+        }
+
+        match self.client.send_raw_transaction(&tx).await {
+            Ok(_) => Ok(Some(TxEvent::Rebroadcast { txid: *txid })),
+            Err(Error::JsonRpcError(err)) => match RejectReason::from_rpc_error(&err) {
+                RejectReason::AlreadyInMempool => Ok(None),
+                reason => {
+                    self.txs.remove(txid);
+                    Ok(Some(TxEvent::Rejected {
+                        txid: *txid,
+                        reason,
+                    }))
+                }
+            },
+            Err(err) => Err(err),
+        }
+    }
+
+    fn set_state(&mut self, txid: &Txid, state: TxState) {
+        if let Some(tracked) = self.txs.get_mut(txid) {
+            tracked.state = state;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rpc_error(code: i64, message: &str) -> JsonRpcError {
+        JsonRpcError {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    #[test]
+    fn classifies_reject_reasons() {
+        assert_eq!(
+            RejectReason::from_reject_reason("txn-mempool-conflict"),
+            RejectReason::MempoolConflict
+        );
+        assert_eq!(
+            RejectReason::from_reject_reason("bad-txns-inputs-missingorspent"),
+            RejectReason::MissingInputs
+        );
+        assert!(matches!(
+            RejectReason::from_reject_reason("min relay fee not met, 100 < 141"),
+            RejectReason::InsufficientFee(_)
+        ));
+        assert_eq!(RejectReason::from_reject_reason("dust"), RejectReason::Dust);
+        assert!(matches!(
+            RejectReason::from_reject_reason("scriptpubkey"),
+            RejectReason::NonStandard(_)
+        ));
+    }
+
+    #[test]
+    fn classifies_rpc_errors() {
+        assert_eq!(
+            RejectReason::from_rpc_error(&rpc_error(-27, "Transaction already in block chain")),
+            RejectReason::AlreadyInChain
+        );
+        assert_eq!(
+            RejectReason::from_rpc_error(&rpc_error(-26, "non-final")),
+            RejectReason::NonFinal
+        );
+        assert_eq!(
+            RejectReason::from_rpc_error(&rpc_error(
+                -25,
+                "Fee exceeds maximum configured by user (e.g. -maxtxfee, maxfeerate)"
+            )),
+            RejectReason::MaxFeeExceeded
+        );
+        assert!(matches!(
+            RejectReason::from_rpc_error(&rpc_error(-22, "TX decode failed")),
+            RejectReason::Invalid(_)
+        ));
+    }
+}
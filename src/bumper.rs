@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+
+use bitcoincore_rpc_json::{
+    bitcoin::{Amount, FeeRate, Txid},
+    Bip125Replaceable, CreateRawTransactionInput, GetMempoolEntryResult, GetTransactionResult,
+};
+
+use crate::{
+    client::{Client, Result},
+    error::Error,
+    types::BumpFeeOptions,
+};
+
+/// Outputs below this value are not worth creating for a CPFP child.
+const DUST_LIMIT: Amount = Amount::from_sat(546);
+
+/// How a stuck transaction was bumped.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BumpMethod {
+    /// The transaction was replaced with `bumpfee` (BIP125).
+    Rbf,
+    /// A child spending one of its wallet-owned outputs was broadcast.
+    Cpfp,
+}
+
+/// The transaction broadcast to get a stuck transaction confirmed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BumpOutcome {
+    pub txid: Txid,
+    pub method: BumpMethod,
+    /// Fee paid by the new transaction.
+    pub fee: Amount,
+}
+
+/// What the wallet and the mempool know about a stuck transaction.
+#[derive(Clone, Debug)]
+pub struct StuckTx {
+    pub txid: Txid,
+    pub mempool_entry: GetMempoolEntryResult,
+    pub wallet_tx: GetTransactionResult,
+    /// Whether the node accepts a replacement, either because the transaction
+    /// signals BIP125 or because the mempool runs with full RBF.
+    pub replaceable: bool,
+    /// Unspent outputs of the transaction that belong to the wallet.
+    pub wallet_outputs: Vec<(u32, Amount)>,
+}
+
+impl StuckTx {
+    /// Fee rate of the transaction together with its unconfirmed ancestors.
+    pub fn package_fee_rate(&self) -> FeeRate {
+        let entry = &self.mempool_entry;
+        FeeRate::from_sat_per_kwu(entry.fees.ancestor.to_sat() * 250 / entry.ancestor_size.max(1))
+    }
+
+    /// Whether the wallet funded the transaction, which `bumpfee` requires.
+    pub fn wallet_funded(&self) -> bool {
+        self.wallet_tx.fee.is_some()
+    }
+
+    /// Whether other mempool transactions spend this one, which prevents
+    /// `bumpfee` from replacing it.
+    pub fn has_descendants(&self) -> bool {
+        self.mempool_entry.descendant_count > 1
+    }
+}
+
+/// Gets stuck wallet transactions confirmed.
+///
+/// Transactions the wallet funded and the node allows to be replaced are
+/// bumped with `bumpfee`. Otherwise, or when the replacement is rejected, a
+/// child paying for the whole package is built from the largest unspent
+/// wallet-owned output (CPFP).
+pub struct FeeBumper<'a> {
+    client: &'a Client,
+}
+
+impl<'a> FeeBumper<'a> {
+    pub fn new(client: &'a Client) -> Self {
+        Self { client }
+    }
+
+    /// Collects what is needed to decide how to bump `txid`.
+    pub async fn inspect(&self, txid: &Txid) -> Result<StuckTx> {
+        let mempool_entry = self.client.get_mempool_entry(txid).await?;
+        let wallet_tx = self.client.get_transaction(txid, Some(true)).await?;
+        let full_rbf = self.client.get_mempool_info().await?.full_rbf == Some(true);
+        let replaceable = full_rbf
+            || mempool_entry.bip125_replaceable
+            || wallet_tx.info.bip125_replaceable == Bip125Replaceable::Yes;
+
+        // Change outputs are left out of the transaction details, so look the
+        // outputs up among the wallet's unspent ones instead.
+        let wallet_outputs = self
+            .client
+            .list_unspent(Some(0), None, None, Some(true), None)
+            .await?
+            .into_iter()
+            .filter(|u| u.txid == *txid && u.spendable)
+            .map(|u| (u.vout, u.amount))
+            .collect();
+
+        Ok(StuckTx {
+            txid: *txid,
+            mempool_entry,
+            wallet_tx,
+            replaceable,
+            wallet_outputs,
+        })
+    }
+
+    /// Bumps `txid` so that it confirms at `fee_rate`, and returns the new
+    /// transaction.
+    ///
+    /// If `bumpfee` is rejected and the CPFP fallback fails too, both errors
+    /// are returned in [Error::BumpFailed].
+    pub async fn bump(&self, txid: &Txid, fee_rate: FeeRate) -> Result<BumpOutcome> {
+        let stuck = self.inspect(txid).await?;
+
+        if stuck.replaceable && stuck.wallet_funded() && !stuck.has_descendants() {
+            let options = BumpFeeOptions {
+                fee_rate: Some(fee_rate),
+                ..Default::default()
+            };
+            match self.client.bump_fee(txid, Some(&options)).await {
+                Ok(res) => {
+                    return Ok(BumpOutcome {
+                        txid: res.txid,
+                        method: BumpMethod::Rbf,
+                        fee: res.fee,
+                    })
+                }
+                Err(err) if stuck.wallet_outputs.is_empty() => return Err(err),
+                // Replacement rules may still reject the bump, fall back to CPFP.
+                Err(rbf) => {
+                    return self
+                        .cpfp(&stuck, fee_rate)
+                        .await
+                        .map_err(|cpfp| Error::BumpFailed {
+                            txid: *txid,
+                            rbf: Box::new(rbf),
+                            cpfp: Box::new(cpfp),
+                        })
+                }
+            }
+        }
+        self.cpfp(&stuck, fee_rate).await
+    }
+
+    /// Broadcasts a child of `stuck` that brings the package to `fee_rate`.
+    pub async fn cpfp(&self, stuck: &StuckTx, fee_rate: FeeRate) -> Result<BumpOutcome> {
+        let Some(&(vout, value)) = stuck.wallet_outputs.iter().max_by_key(|(_, v)| *v) else {
+            return Err(Error::CannotBump {
+                txid: stuck.txid,
+                reason: "no unspent wallet-owned output to spend".into(),
+            });
+        };
+        let address = self
+            .client
+            .get_raw_change_address(None)
+            .await?
+            .assume_checked()
+            .to_string();
+        let inputs = [CreateRawTransactionInput {
+            txid: stuck.txid,
+            vout,
+            sequence: None,
+        }];
+
+        // Sign once to learn the child's size; amounts do not change it.
+        let draft = self
+            .sign_child(&inputs, &address, value.min(DUST_LIMIT), stuck)
+            .await?;
+        let fee = cpfp_child_fee(
+            stuck.mempool_entry.fees.ancestor,
+            stuck.mempool_entry.ancestor_size,
+            draft.vsize() as u64,
+            fee_rate,
+        );
+        let amount = value
+            .checked_sub(fee)
+            .filter(|a| *a >= DUST_LIMIT)
+            .ok_or_else(|| Error::CannotBump {
+                txid: stuck.txid,
+                reason: format!("output of {value} cannot pay a child fee of {fee}"),
+            })?;
+
+        let child = self.sign_child(&inputs, &address, amount, stuck).await?;
+        let txid = self.client.send_raw_transaction(&child).await?;
+        Ok(BumpOutcome {
+            txid,
+            method: BumpMethod::Cpfp,
+            fee,
+        })
+    }
+
+    async fn sign_child(
+        &self,
+        inputs: &[CreateRawTransactionInput],
+        address: &str,
+        amount: Amount,
+        stuck: &StuckTx,
+    ) -> Result<bitcoincore_rpc_json::bitcoin::Transaction> {
+        let outputs = HashMap::from([(address.to_string(), amount)]);
+        let unsigned = self
+            .client
+            .create_raw_transaction(inputs, &outputs, None, Some(true))
+            .await?;
+        let signed = self
+            .client
+            .sign_raw_transaction_with_wallet(&unsigned, None, None)
+            .await?;
+        if !signed.complete {
+            return Err(Error::CannotBump {
+                txid: stuck.txid,
+                reason: "wallet could not sign the child transaction".into(),
+            });
+        }
+        Ok(signed.transaction()?)
+    }
+}
+
+/// Fee a child of size `child_vsize` must pay for the package made of it and
+/// its unconfirmed ancestors to reach `fee_rate`.
+fn cpfp_child_fee(
+    ancestor_fee: Amount,
+    ancestor_vsize: u64,
+    child_vsize: u64,
+    fee_rate: FeeRate,
+) -> Amount {
+    let package_fee = fee_rate
+        .fee_vb(ancestor_vsize + child_vsize)
+        .unwrap_or(Amount::MAX_MONEY);
+    let min_fee = FeeRate::BROADCAST_MIN
+        .fee_vb(child_vsize)
+        .unwrap_or(Amount::MAX_MONEY);
+    package_fee
+        .checked_sub(ancestor_fee)
+        .unwrap_or(Amount::ZERO)
+        .max(min_fee)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn child_pays_for_package() {
+        let rate = FeeRate::from_sat_per_vb_unchecked(20);
+        // Parent of 200 vB paying 1 sat/vB, child of 110 vB.
+        let fee = cpfp_child_fee(Amount::from_sat(200), 200, 110, rate);
+        assert_eq!(fee, Amount::from_sat(20 * 310 - 200));
+    }
+
+    #[test]
+    fn child_pays_at_least_min_relay_fee() {
+        let rate = FeeRate::from_sat_per_vb_unchecked(5);
+        let fee = cpfp_child_fee(Amount::from_sat(100_000), 200, 110, rate);
+        assert_eq!(fee, Amount::from_sat(110));
+    }
+}
@@ -0,0 +1,372 @@
+use std::{
+    hash::Hash,
+    num::NonZeroUsize,
+    ops::Deref,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use bitcoincore_rpc_json::{
+    self as json,
+    bitcoin::{block::Header, Block, BlockHash, Transaction, Txid},
+};
+use lru::LruCache;
+use tokio::{task::JoinHandle, time::interval};
+
+use crate::client::{Client, Result};
+
+/// A [Client] caching the responses of frequent reads.
+///
+/// Immutable data is kept in size-bounded LRU caches: blocks and headers by
+/// hash, and confirmed transactions by txid. Data depending on the chain tip
+/// (the block count, best block hash, mempool info and header confirmations)
+/// is kept for a short time-to-live, and dropped as soon as the tip is seen
+/// to change, either from the responses of the node, through
+/// [CachedClient::set_tip], or by [CachedClient::watch_tip]. Once the tip is
+/// tracked through either of the latter, header infos are kept until the tip
+/// changes regardless of the time-to-live.
+///
+/// Other calls go straight to the wrapped client, which the cache
+/// dereferences to.
+pub struct CachedClient {
+    client: Arc<Client>,
+    ttl: Duration,
+    blocks: Mutex<LruCache<BlockHash, Block>>,
+    headers: Mutex<LruCache<BlockHash, Header>>,
+    txs: Mutex<LruCache<Txid, Transaction>>,
+    volatile: Mutex<Volatile>,
+}
+
+/// Tip-dependent entries, cleared when the tip changes.
+struct Volatile {
+    tip: Option<BlockHash>,
+    /// Bumped whenever the entries are dropped, so that responses fetched
+    /// before are not cached after.
+    generation: u64,
+    /// Whether the tip is reported through [CachedClient::set_tip].
+    tracked: bool,
+    block_count: Option<Expiring<u64>>,
+    best_block_hash: Option<Expiring<BlockHash>>,
+    mempool_info: Option<Expiring<json::GetMempoolInfoResult>>,
+    header_infos: LruCache<BlockHash, Expiring<json::GetBlockHeaderResult>>,
+}
+
+struct Expiring<T> {
+    value: T,
+    fetched: Instant,
+}
+
+impl<T: Clone> Expiring<T> {
+    fn new(value: T) -> Self {
+        Self {
+            value,
+            fetched: Instant::now(),
+        }
+    }
+
+    fn fresh(&self, ttl: Duration) -> Option<T> {
+        (self.fetched.elapsed() < ttl).then(|| self.value.clone())
+    }
+}
+
+impl Volatile {
+    /// Records `tip`, dropping every entry if it changed.
+    fn observe_tip(&mut self, tip: BlockHash) {
+        if self.tip != Some(tip) {
+            self.tip = Some(tip);
+            self.clear();
+        }
+    }
+
+    fn clear(&mut self) {
+        self.generation += 1;
+        self.block_count = None;
+        self.best_block_hash = None;
+        self.mempool_info = None;
+        self.header_infos.clear();
+    }
+}
+
+fn cap(n: usize) -> NonZeroUsize {
+    NonZeroUsize::new(n.max(1)).expect("at least 1")
+}
+
+fn get<K: Hash + Eq, V: Clone>(cache: &Mutex<LruCache<K, V>>, key: &K) -> Option<V> {
+    cache.lock().expect("poisoned").get(key).cloned()
+}
+
+fn put<K: Hash + Eq, V>(cache: &Mutex<LruCache<K, V>>, key: K, value: V) {
+    cache.lock().expect("poisoned").put(key, value);
+}
+
+impl CachedClient {
+    /// Wraps `client`, caching 64 blocks, 10000 headers and 10000
+    /// transactions, and tip-dependent data for one second.
+    pub fn new(client: Arc<Client>) -> Self {
+        Self {
+            client,
+            ttl: Duration::from_secs(1),
+            blocks: Mutex::new(LruCache::new(cap(64))),
+            headers: Mutex::new(LruCache::new(cap(10_000))),
+            txs: Mutex::new(LruCache::new(cap(10_000))),
+            volatile: Mutex::new(Volatile {
+                tip: None,
+                generation: 0,
+                tracked: false,
+                block_count: None,
+                best_block_hash: None,
+                mempool_info: None,
+                header_infos: LruCache::new(cap(10_000)),
+            }),
+        }
+    }
+
+    /// Sets how many blocks are cached.
+    pub fn block_capacity(self, capacity: usize) -> Self {
+        self.blocks.lock().expect("poisoned").resize(cap(capacity));
+        self
+    }
+
+    /// Sets how many headers, and header infos, are cached.
+    pub fn header_capacity(self, capacity: usize) -> Self {
+        self.headers.lock().expect("poisoned").resize(cap(capacity));
+        self.volatile
+            .lock()
+            .expect("poisoned")
+            .header_infos
+            .resize(cap(capacity));
+        self
+    }
+
+    /// Sets how many confirmed transactions are cached.
+    pub fn tx_capacity(self, capacity: usize) -> Self {
+        self.txs.lock().expect("poisoned").resize(cap(capacity));
+        self
+    }
+
+    /// Sets how long tip-dependent data is kept while the tip is unchanged.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Returns the wrapped client.
+    pub fn client(&self) -> &Arc<Client> {
+        &self.client
+    }
+
+    /// Records `tip` as the chain tip, dropping the tip-dependent data if it
+    /// changed, e.g. on a ZMQ "hashblock" notification.
+    pub fn set_tip(&self, tip: BlockHash) {
+        let mut volatile = self.volatile.lock().expect("poisoned");
+        volatile.tracked = true;
+        volatile.observe_tip(tip);
+    }
+
+    /// Drops every cached entry.
+    pub fn clear(&self) {
+        self.blocks.lock().expect("poisoned").clear();
+        self.headers.lock().expect("poisoned").clear();
+        self.txs.lock().expect("poisoned").clear();
+        let mut volatile = self.volatile.lock().expect("poisoned");
+        volatile.tip = None;
+        volatile.tracked = false;
+        volatile.clear();
+    }
+
+    /// Polls the best block hash every `poll_interval` on a spawned task,
+    /// so that tip-dependent data is dropped soon after the tip changes.
+    ///
+    /// Must be called from within a Tokio runtime. Errors are ignored until
+    /// the next poll; abort the returned handle to stop polling.
+    pub fn watch_tip(self: &Arc<Self>, poll_interval: Duration) -> JoinHandle<()> {
+        let cache = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = interval(poll_interval);
+            loop {
+                ticker.tick().await;
+                let Some(cache) = cache.upgrade() else {
+                    return;
+                };
+                if let Ok(tip) = cache.client.get_best_block_hash().await {
+                    cache.set_tip(tip);
+                }
+            }
+        })
+    }
+
+    /// Returns the block with `hash`, from the cache if possible.
+    pub async fn get_block(&self, hash: &BlockHash) -> Result<Block> {
+        if let Some(block) = get(&self.blocks, hash) {
+            return Ok(block);
+        }
+        let block = self.client.get_block(hash).await?;
+        put(&self.blocks, *hash, block.clone());
+        Ok(block)
+    }
+
+    /// Returns the header of the block with `hash`, from the cache if
+    /// possible.
+    pub async fn get_block_header(&self, hash: &BlockHash) -> Result<Header> {
+        if let Some(header) = get(&self.headers, hash) {
+            return Ok(header);
+        }
+        let header = self.client.get_block_header(hash).await?;
+        put(&self.headers, *hash, header);
+        Ok(header)
+    }
+
+    /// Returns the header info of the block with `hash`.
+    ///
+    /// Its confirmations and next block depend on the tip, so it is dropped
+    /// when the tip changes, and after the time-to-live unless the tip is
+    /// tracked.
+    pub async fn get_block_header_info(
+        &self,
+        hash: &BlockHash,
+    ) -> Result<json::GetBlockHeaderResult> {
+        let generation = {
+            let mut volatile = self.volatile.lock().expect("poisoned");
+            let ttl = if volatile.tracked {
+                Duration::MAX
+            } else {
+                self.ttl
+            };
+            if let Some(info) = volatile.header_infos.get(hash).and_then(|e| e.fresh(ttl)) {
+                return Ok(info);
+            }
+            volatile.generation
+        };
+        let info = self.client.get_block_header_info(hash).await?;
+        self.update(generation, |v| {
+            v.header_infos.put(*hash, Expiring::new(info.clone()));
+        });
+        Ok(info)
+    }
+
+    /// Returns the transaction with `txid`, from the cache if possible.
+    ///
+    /// Only confirmed transactions are cached, since the witness of an
+    /// unconfirmed one may still change.
+    pub async fn get_raw_transaction(
+        &self,
+        txid: &Txid,
+        block_hash: Option<&BlockHash>,
+    ) -> Result<Transaction> {
+        if let Some(tx) = get(&self.txs, txid) {
+            return Ok(tx);
+        }
+        let info = self
+            .client
+            .get_raw_transaction_info(txid, block_hash)
+            .await?;
+        let tx = info.transaction()?;
+        if info.blockhash.is_some() {
+            put(&self.txs, *txid, tx.clone());
+        }
+        Ok(tx)
+    }
+
+    /// Returns the height of the tip, cached for the time-to-live.
+    pub async fn get_block_count(&self) -> Result<u64> {
+        let generation = match self.fresh(|v| &v.block_count) {
+            Ok(count) => return Ok(count),
+            Err(generation) => generation,
+        };
+        let count = self.client.get_block_count().await?;
+        self.update(generation, |v| v.block_count = Some(Expiring::new(count)));
+        Ok(count)
+    }
+
+    /// Returns the hash of the tip, cached for the time-to-live.
+    pub async fn get_best_block_hash(&self) -> Result<BlockHash> {
+        let generation = match self.fresh(|v| &v.best_block_hash) {
+            Ok(hash) => return Ok(hash),
+            Err(generation) => generation,
+        };
+        let hash = self.client.get_best_block_hash().await?;
+        // A tip fetched before the tip last changed may already be stale.
+        self.update(generation, |v| {
+            v.observe_tip(hash);
+            v.best_block_hash = Some(Expiring::new(hash));
+        });
+        Ok(hash)
+    }
+
+    /// Returns the mempool info, cached for the time-to-live.
+    pub async fn get_mempool_info(&self) -> Result<json::GetMempoolInfoResult> {
+        let generation = match self.fresh(|v| &v.mempool_info) {
+            Ok(info) => return Ok(info),
+            Err(generation) => generation,
+        };
+        let info = self.client.get_mempool_info().await?;
+        self.update(generation, |v| {
+            v.mempool_info = Some(Expiring::new(info.clone()))
+        });
+        Ok(info)
+    }
+
+    /// Returns the entry if fresh, or else the generation to fetch it at.
+    fn fresh<T: Clone>(
+        &self,
+        entry: impl FnOnce(&Volatile) -> &Option<Expiring<T>>,
+    ) -> std::result::Result<T, u64> {
+        let volatile = self.volatile.lock().expect("poisoned");
+        let fresh = entry(&volatile).as_ref().and_then(|e| e.fresh(self.ttl));
+        fresh.ok_or(volatile.generation)
+    }
+
+    /// Caches a response with `update`, unless the entries were dropped
+    /// since `generation`, while it was being fetched.
+    fn update(&self, generation: u64, update: impl FnOnce(&mut Volatile)) {
+        let mut volatile = self.volatile.lock().expect("poisoned");
+        if volatile.generation == generation {
+            update(&mut volatile);
+        }
+    }
+}
+
+impl Deref for CachedClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.client
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::Auth;
+    use bitcoincore_rpc_json::bitcoin::{constants::genesis_block, hashes::Hash, Network};
+
+    #[tokio::test]
+    async fn serves_cached_entries_until_the_tip_changes() {
+        // Nothing listens on this port: every hit must come from the cache.
+        let client = Arc::new(Client::new("http://127.0.0.1:9", Auth::None).unwrap());
+        let cache = CachedClient::new(client).ttl(Duration::from_secs(60));
+
+        let genesis = genesis_block(Network::Regtest);
+        let hash = genesis.block_hash();
+        put(&cache.blocks, hash, genesis.clone());
+        assert_eq!(cache.get_block(&hash).await.unwrap(), genesis);
+
+        cache.set_tip(hash);
+        cache.volatile.lock().unwrap().block_count = Some(Expiring::new(0));
+        assert_eq!(cache.get_block_count().await.unwrap(), 0);
+
+        cache.set_tip(hash);
+        assert_eq!(cache.get_block_count().await.unwrap(), 0);
+        cache.set_tip(BlockHash::all_zeros());
+        assert!(cache.get_block_count().await.is_err());
+        assert_eq!(cache.get_block(&hash).await.unwrap(), genesis);
+
+        // A count fetched before the tip changed must not be cached after.
+        let Err(generation) = cache.fresh(|v| &v.block_count) else {
+            panic!("cleared by the tip change");
+        };
+        cache.set_tip(hash);
+        cache.update(generation, |v| v.block_count = Some(Expiring::new(0)));
+        assert!(cache.get_block_count().await.is_err());
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::VecDeque;
+
+use bitcoincore_rpc_json::bitcoin::BlockHash;
+
+use crate::{
+    client::{Client, Result},
+    error::Error,
+};
+
+/// How many blocks a follower remembers by default to detect reorgs.
+pub const DEFAULT_MAX_REORG_DEPTH: usize = 144;
+
+/// A change to the best chain, as seen by [ChainFollower].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChainEvent {
+    /// The block was added to the best chain.
+    Connected { height: u64, hash: BlockHash },
+    /// The block was removed from the best chain by a reorg.
+    Disconnected { height: u64, hash: BlockHash },
+}
+
+/// Follows the node's best chain by polling, reporting connected and
+/// disconnected blocks in order.
+///
+/// The hashes of the most recent blocks are remembered to find the fork point
+/// when the node switches to another chain. Disconnections are reported from
+/// the old tip down, followed by the connections of the new branch.
+#[derive(Clone, Debug)]
+pub struct ChainFollower {
+    /// Height of the first entry of `hashes`.
+    base_height: u64,
+    hashes: VecDeque<BlockHash>,
+    max_reorg_depth: usize,
+}
+
+impl ChainFollower {
+    /// Creates a follower whose last processed block is `hash` at `height`.
+    pub fn new(height: u64, hash: BlockHash) -> Self {
+        Self {
+            base_height: height,
+            hashes: VecDeque::from([hash]),
+            max_reorg_depth: DEFAULT_MAX_REORG_DEPTH,
+        }
+    }
+
+    /// Creates a follower starting at the node's current tip.
+    pub async fn from_tip(client: &Client) -> Result<Self> {
+        let height = client.get_block_count().await?;
+        let hash = client.get_block_hash(height).await?;
+        Ok(Self::new(height, hash))
+    }
+
+    /// Sets how many blocks are remembered to detect reorgs.
+    pub fn max_reorg_depth(mut self, depth: usize) -> Self {
+        self.max_reorg_depth = depth.max(1);
+        self
+    }
+
+    /// Height and hash of the last processed block.
+    pub fn tip(&self) -> (u64, BlockHash) {
+        let hash = *self.hashes.back().expect("follower is never empty");
+        (self.base_height + self.hashes.len() as u64 - 1, hash)
+    }
+
+    /// Hash of the processed block at `height`, if still remembered.
+    pub fn hash_at(&self, height: u64) -> Option<BlockHash> {
+        let idx = height.checked_sub(self.base_height)?;
+        self.hashes.get(idx as usize).copied()
+    }
+
+    /// Catches up with the node's best chain.
+    ///
+    /// Fails with [Error::ReorgTooDeep] if the fork point is older than the
+    /// blocks remembered.
+    pub async fn poll(&mut self, client: &Client) -> Result<Vec<ChainEvent>> {
+        let node_height = client.get_block_count().await?;
+        let (tip_height, _) = self.tip();
+
+        let mut fork = tip_height.min(node_height);
+        loop {
+            let Some(ours) = self.hash_at(fork) else {
+                return Err(Error::ReorgTooDeep {
+                    depth: tip_height - fork,
+                });
+            };
+            if client.get_block_hash(fork).await? == ours {
+                break;
+            }
+            // Even the first block differs: the node follows another chain.
+            fork = fork.checked_sub(1).ok_or(Error::ReorgTooDeep {
+                depth: tip_height + 1,
+            })?;
+        }
+
+        let mut events = Vec::new();
+        for height in (fork + 1..=tip_height).rev() {
+            let hash = self.hashes.pop_back().expect("height is remembered");
+            events.push(ChainEvent::Disconnected { height, hash });
+        }
+        for height in fork + 1..=node_height {
+            let hash = client.get_block_hash(height).await?;
+            self.hashes.push_back(hash);
+            events.push(ChainEvent::Connected { height, hash });
+        }
+
+        while self.hashes.len() > self.max_reorg_depth {
+            self.hashes.pop_front();
+            self.base_height += 1;
+        }
+        Ok(events)
+    }
+}
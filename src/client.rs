@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
 
 use bitcoincore_rpc::{Auth, JsonOutPoint, RawTx};
 use bitcoincore_rpc_json as json;
@@ -8,12 +8,24 @@ use json::bitcoin::{
     consensus::encode,
     ecdsa::Signature,
     hex::{DisplayHex, FromHex},
-    Address, Amount, Block, OutPoint, PrivateKey, PublicKey, Script, Transaction,
+    sign_message, Address, Amount, Block, OutPoint, PrivateKey, PublicKey, Script, Transaction,
 };
 use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
 use url::Url;
 
-use crate::{error::Error, relay::Relay};
+#[cfg(feature = "store")]
+use crate::store::BlockStore;
+use crate::{
+    compat::{Adapter, Compat},
+    error::Error,
+    help::HelpIndex,
+    merkle, message,
+    relay::Relay,
+    txindex::TxIndex,
+    types,
+    unlock::{self, AutoUnlock, RelockOnDrop, SecretProvider},
+};
 
 /// Crate-specific Result type, shorthand for `std::result::Result` with our
 /// crate-specific Error type;
@@ -22,6 +34,12 @@ pub type Result<T> = std::result::Result<T, Error>;
 /// Client implements a JSON-RPC client for the Bitcoin Core daemon or compatible APIs.
 pub struct Client {
     relay: Relay,
+    auto_unlock: Option<AutoUnlock>,
+    compat: Compat,
+    version: OnceCell<usize>,
+    #[cfg(feature = "store")]
+    store: Option<std::sync::Arc<BlockStore>>,
+    tx_index: Option<std::sync::Arc<TxIndex>>,
 }
 
 impl Client {
@@ -32,17 +50,173 @@ impl Client {
         let (user, pass) = auth.get_user_pass()?;
         Ok(Self {
             relay: Relay::new(Url::parse(url)?, user, pass),
+            auto_unlock: None,
+            compat: Compat::default(),
+            version: OnceCell::new(),
+            #[cfg(feature = "store")]
+            store: None,
+            tx_index: None,
         })
     }
 
+    /// Unlocks the wallet for `timeout` with the passphrase from `provider`
+    /// when a call fails because the wallet is locked, retries the call once,
+    /// then locks the wallet again.
+    ///
+    /// Other calls running meanwhile may see the wallet locked again before
+    /// they complete; they are retried the same way.
+    pub fn auto_unlock(
+        mut self,
+        provider: impl SecretProvider + 'static,
+        timeout: Duration,
+    ) -> Self {
+        self.auto_unlock = Some(AutoUnlock {
+            provider: Box::new(provider),
+            timeout,
+        });
+        self
+    }
+
+    /// Replaces the table of adapters applied to calls, [Compat::default]
+    /// unless set.
+    pub fn compat(mut self, compat: Compat) -> Self {
+        self.compat = compat;
+        self
+    }
+
+    /// Adds `adapter` to the adapters applied to calls.
+    pub fn adapter(mut self, adapter: Adapter) -> Self {
+        self.compat.register(adapter);
+        self
+    }
+
+    /// Reads blocks and headers through `store`: they are looked up there
+    /// first, and stored once fetched from the node. Block hashes fetched by
+    /// height are recorded in its height index.
+    #[cfg(feature = "store")]
+    pub fn store(mut self, store: std::sync::Arc<BlockStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Looks up the block of transactions in `index` when no block hash is
+    /// given to [Self::get_raw_transaction] and its variants, so that they
+    /// can be served without `-txindex` on the node.
+    pub fn tx_index(mut self, index: std::sync::Arc<TxIndex>) -> Self {
+        self.tx_index = Some(index);
+        self
+    }
+
+    /// Calls `cmd`, through the adapters registered for it and the server
+    /// version.
     pub async fn call<T: for<'a> serde::de::Deserialize<'a>>(
         &self,
         cmd: &str,
         args: &[serde_json::Value],
     ) -> Result<T> {
-        self.relay
+        if !self.compat.has(cmd) {
+            return self.call_unadapted(cmd, args).await;
+        }
+        let version = self.version().await?;
+        if self.compat.is_removed(cmd, version) {
+            return Err(Error::Unsupported {
+                method: cmd.to_owned(),
+                server_version: version,
+            });
+        }
+        let mut args = args.to_vec();
+        self.compat.adapt_request(cmd, version, &mut args)?;
+        let res = self.call_unadapted(cmd, &args).await?;
+        let res = self.compat.adapt_response(cmd, version, res)?;
+        Ok(serde_json::from_value(res)?)
+    }
+
+    async fn call_unadapted<T: for<'a> serde::de::Deserialize<'a>>(
+        &self,
+        cmd: &str,
+        args: &[serde_json::Value],
+    ) -> Result<T> {
+        let res = self
+            .relay
             .request::<&[serde_json::Value], _>(cmd, args)
+            .await;
+        match (&self.auto_unlock, res) {
+            (Some(auto), Err(err)) if unlock::is_unlock_needed(&err) => {
+                let retry = self.relay.request::<&[serde_json::Value], _>(cmd, args);
+                match self.auto_unlocked(auto, retry).await {
+                    Some(res) => res,
+                    None => Err(err),
+                }
+            }
+            (_, res) => res,
+        }
+    }
+
+    /// Unlocks the wallet with the passphrase of `auto`, runs `retry` and
+    /// locks the wallet again, even if `retry` is cancelled.
+    ///
+    /// Returns `None` if the provider gives no passphrase.
+    async fn auto_unlocked<T>(
+        &self,
+        auto: &AutoUnlock,
+        retry: impl std::future::Future<Output = Result<T>>,
+    ) -> Option<Result<T>> {
+        let passphrase = auto.provider.passphrase()?;
+        let res = async {
+            self.relay
+                .request::<_, ()>(
+                    "walletpassphrase",
+                    [
+                        serde_json::Value::from(passphrase),
+                        unlock::timeout_secs(auto.timeout).into(),
+                    ],
+                )
+                .await?;
+            let relock = RelockOnDrop::new(&self.relay);
+            let res = retry.await;
+            let locked = self.relay.request::<_, ()>("walletlock", ()).await;
+            relock.disarm();
+            let value = res?;
+            locked?;
+            Ok(value)
+        };
+        Some(res.await)
+    }
+
+    pub(crate) fn relay(&self) -> &Relay {
+        &self.relay
+    }
+
+    /// Sends several calls in a single JSON-RPC batch request.
+    ///
+    /// Results are returned in the order of `calls`; each one fails on its
+    /// own if the server returned an error for it. Unlike [Self::call], no
+    /// adapter is applied. Calls failing because the wallet is locked are
+    /// retried in a second batch when [Self::auto_unlock] is set.
+    pub async fn call_batch<T: for<'a> serde::de::Deserialize<'a>>(
+        &self,
+        calls: &[(&str, &[serde_json::Value])],
+    ) -> Result<Vec<Result<T>>> {
+        let mut results = self.relay.batch_request(calls).await?;
+        let Some(auto) = &self.auto_unlock else {
+            return Ok(results);
+        };
+        let locked: Vec<usize> = (0..results.len())
+            .filter(|&i| matches!(&results[i], Err(err) if unlock::is_unlock_needed(err)))
+            .collect();
+        if locked.is_empty() {
+            return Ok(results);
+        }
+        let retry: Vec<_> = locked.iter().map(|&i| calls[i]).collect();
+        if let Some(retried) = self
+            .auto_unlocked(auto, self.relay.batch_request(&retry))
             .await
+        {
+            for (i, res) in locked.into_iter().zip(retried?) {
+                results[i] = res;
+            }
+        }
+        Ok(results)
     }
 
     pub async fn get_network_info(&self) -> Result<json::GetNetworkInfoResult> {
@@ -53,13 +227,31 @@ impl Client {
         self.call("getindexinfo", &[]).await
     }
 
+    /// Returns the server version, e.g. 280000 for 28.0.
+    ///
+    /// The version is fetched once and cached for the life of the client.
     pub async fn version(&self) -> Result<usize> {
         #[derive(Deserialize)]
         struct Response {
             pub version: usize,
         }
-        let res: Response = self.call("getnetworkinfo", &[]).await?;
-        Ok(res.version)
+        let version = self
+            .version
+            .get_or_try_init(|| async {
+                let res: Response = self.call_unadapted("getnetworkinfo", &[]).await?;
+                Ok::<_, Error>(res.version)
+            })
+            .await?;
+        Ok(*version)
+    }
+
+    /// Returns true if the server no longer has `method`, according to the
+    /// table of removed methods.
+    pub async fn is_removed(&self, method: &str) -> Result<bool> {
+        if !self.compat.has(method) {
+            return Ok(false);
+        }
+        Ok(self.compat.is_removed(method, self.version().await?))
     }
 
     pub async fn add_multisig_address(
@@ -102,22 +294,81 @@ impl Client {
         blank: Option<bool>,
         passphrase: Option<&str>,
         avoid_reuse: Option<bool>,
+    ) -> Result<json::LoadWalletResult> {
+        let options = types::CreateWalletOptions {
+            disable_private_keys,
+            blank,
+            passphrase: passphrase.map(Into::into),
+            avoid_reuse,
+            ..Default::default()
+        };
+        self.create_wallet_with_options(wallet, &options).await
+    }
+
+    /// Creates and loads the wallet named `wallet`, with all the options
+    /// of "createwallet".
+    pub async fn create_wallet_with_options(
+        &self,
+        wallet: &str,
+        options: &types::CreateWalletOptions,
     ) -> Result<json::LoadWalletResult> {
         let mut args = [
             wallet.into(),
-            opt_into_json(disable_private_keys)?,
-            opt_into_json(blank)?,
-            opt_into_json(passphrase)?,
-            opt_into_json(avoid_reuse)?,
+            opt_into_json(options.disable_private_keys)?,
+            opt_into_json(options.blank)?,
+            opt_into_json(options.passphrase.as_deref())?,
+            opt_into_json(options.avoid_reuse)?,
+            opt_into_json(options.descriptors)?,
+            opt_into_json(options.load_on_startup)?,
+            opt_into_json(options.external_signer)?,
         ];
-        self.call(
-            "createwallet",
-            handle_defaults(
-                &mut args,
-                &[false.into(), false.into(), into_json("")?, false.into()],
-            ),
-        )
-        .await
+        let len = trim_nulls(&args).len();
+        // The node takes null as its own default for "descriptors", false
+        // before v23 and true since, and for "load_on_startup".
+        let defaults = [
+            null(),
+            false.into(),
+            false.into(),
+            into_json("")?,
+            false.into(),
+            null(),
+            null(),
+        ];
+        for (arg, default) in args[..len].iter_mut().zip(defaults) {
+            if arg.is_null() {
+                *arg = default;
+            }
+        }
+        self.call("createwallet", &args[..len]).await
+    }
+
+    /// Restores a wallet from a backup file into a new wallet named
+    /// `wallet`, and loads it.
+    pub async fn restore_wallet(
+        &self,
+        wallet: &str,
+        backup_file: &str,
+        load_on_startup: Option<bool>,
+    ) -> Result<json::LoadWalletResult> {
+        let args = [
+            wallet.into(),
+            backup_file.into(),
+            opt_into_json(load_on_startup)?,
+        ];
+        self.call("restorewallet", trim_nulls(&args)).await
+    }
+
+    /// Migrates a legacy wallet to a descriptor wallet. Migrates the wallet
+    /// of the endpoint if `wallet` is `None`.
+    ///
+    /// Requires Core v24 or later.
+    pub async fn migrate_wallet(
+        &self,
+        wallet: Option<&str>,
+        passphrase: Option<&str>,
+    ) -> Result<types::MigrateWalletResult> {
+        let args = [opt_into_json(wallet)?, opt_into_json(passphrase)?];
+        self.call("migratewallet", trim_nulls(&args)).await
     }
 
     pub async fn list_wallets(&self) -> Result<Vec<String>> {
@@ -149,6 +400,32 @@ impl Client {
         self.call("encryptwallet", &[into_json(passphrase)?]).await
     }
 
+    /// Unlocks the wallet for `timeout`, rounded up to whole seconds.
+    pub async fn wallet_passphrase(&self, passphrase: &str, timeout: Duration) -> Result<()> {
+        self.call(
+            "walletpassphrase",
+            &[
+                into_json(passphrase)?,
+                into_json(unlock::timeout_secs(timeout))?,
+            ],
+        )
+        .await
+    }
+
+    /// Locks the wallet, forgetting its decryption key.
+    pub async fn wallet_lock(&self) -> Result<()> {
+        self.call("walletlock", &[]).await
+    }
+
+    /// Changes the wallet passphrase from `old` to `new`.
+    pub async fn wallet_passphrase_change(&self, old: &str, new: &str) -> Result<()> {
+        self.call(
+            "walletpassphrasechange",
+            &[into_json(old)?, into_json(new)?],
+        )
+        .await
+    }
+
     pub async fn get_difficulty(&self) -> Result<f64> {
         self.call("getdifficulty", &[]).await
     }
@@ -158,14 +435,69 @@ impl Client {
     }
 
     pub async fn get_block(&self, hash: &bitcoin::BlockHash) -> Result<Block> {
-        let hex: String = self.call("getblock", &[into_json(hash)?, 0.into()]).await?;
+        let hex = self.get_block_hex(hash).await?;
         Ok(encode::deserialize_hex(&hex).map_err(bitcoincore_rpc::Error::from)?)
     }
 
     pub async fn get_block_hex(&self, hash: &bitcoin::BlockHash) -> Result<String> {
+        #[cfg(feature = "store")]
+        if let Some(store) = &self.store {
+            let hash = *hash;
+            if let Some(block) = store.run(move |s| s.block_bytes(&hash)).await? {
+                return Ok(block.to_lower_hex_string());
+            }
+            let hex: String = self.call("getblock", &[into_json(hash)?, 0.into()]).await?;
+            let bytes =
+                Vec::from_hex(&hex).map_err(|_| bitcoincore_rpc::Error::UnexpectedStructure)?;
+            store.run(move |s| s.put_block_bytes(&hash, &bytes)).await?;
+            return Ok(hex);
+        }
         self.call("getblock", &[into_json(hash)?, 0.into()]).await
     }
 
+    /// Returns the blocks with `hashes`, in order, fetching them from the
+    /// node in a single JSON-RPC batch.
+    ///
+    /// Fails with the first error returned for any of the blocks.
+    pub async fn get_blocks(&self, hashes: &[bitcoin::BlockHash]) -> Result<Vec<Block>> {
+        let mut blocks: Vec<Option<Block>> = vec![None; hashes.len()];
+        #[cfg(feature = "store")]
+        if let Some(store) = &self.store {
+            let hashes = hashes.to_vec();
+            blocks = store
+                .run(move |s| hashes.iter().map(|h| s.block(h)).collect())
+                .await?;
+        }
+        let missing: Vec<usize> = (0..hashes.len()).filter(|&i| blocks[i].is_none()).collect();
+        let args = missing
+            .iter()
+            .map(|&i| Ok([into_json(hashes[i])?, 0.into()]))
+            .collect::<Result<Vec<_>>>()?;
+        let calls: Vec<(&str, &[serde_json::Value])> =
+            args.iter().map(|a| ("getblock", a.as_slice())).collect();
+        let results: Vec<Result<String>> = self.call_batch(&calls).await?;
+        #[cfg(feature = "store")]
+        let mut fetched = Vec::with_capacity(missing.len());
+        for (i, hex) in missing.into_iter().zip(results) {
+            let bytes =
+                Vec::from_hex(&hex?).map_err(|_| bitcoincore_rpc::Error::UnexpectedStructure)?;
+            blocks[i] = Some(encode::deserialize(&bytes)?);
+            #[cfg(feature = "store")]
+            fetched.push((hashes[i], bytes));
+        }
+        #[cfg(feature = "store")]
+        if let Some(store) = &self.store {
+            store
+                .run(move |s| {
+                    fetched
+                        .iter()
+                        .try_for_each(|(hash, bytes)| s.put_block_bytes(hash, bytes))
+                })
+                .await?;
+        }
+        Ok(blocks.into_iter().map(|b| b.expect("fetched")).collect())
+    }
+
     pub async fn get_block_info(&self, hash: &bitcoin::BlockHash) -> Result<json::GetBlockResult> {
         self.call("getblock", &[into_json(hash)?, 1.into()]).await
     }
@@ -175,10 +507,23 @@ impl Client {
         &self,
         hash: &bitcoin::BlockHash,
     ) -> Result<bitcoin::block::Header> {
+        #[cfg(feature = "store")]
+        if let Some(store) = &self.store {
+            let hash = *hash;
+            if let Some(header) = store.run(move |s| s.header(&hash)).await? {
+                return Ok(header);
+            }
+        }
         let hex: String = self
             .call("getblockheader", &[into_json(hash)?, false.into()])
             .await?;
-        Ok(encode::deserialize_hex(&hex).map_err(bitcoincore_rpc::Error::from)?)
+        let header: bitcoin::block::Header =
+            encode::deserialize_hex(&hex).map_err(bitcoincore_rpc::Error::from)?;
+        #[cfg(feature = "store")]
+        if let Some(store) = &self.store {
+            store.run(move |s| s.put_header(&header)).await?;
+        }
+        Ok(header)
     }
 
     pub async fn get_block_header_info(
@@ -217,77 +562,54 @@ impl Client {
         .await
     }
 
+    /// Waits for a template differing from the one `longpollid` identifies,
+    /// returned once the tip changes or, after a while, new transactions
+    /// entered the mempool.
+    pub async fn get_block_template_longpoll(
+        &self,
+        rules: &[json::GetBlockTemplateRules],
+        longpollid: &str,
+    ) -> Result<json::GetBlockTemplateResult> {
+        #[derive(Serialize)]
+        struct Argument<'a> {
+            rules: &'a [json::GetBlockTemplateRules],
+            longpollid: &'a str,
+        }
+
+        self.call(
+            "getblocktemplate",
+            &[into_json(Argument { rules, longpollid })?],
+        )
+        .await
+    }
+
+    /// Checks `block` in "getblocktemplate" proposal mode, without checking
+    /// its proof of work.
+    ///
+    /// Returns `None` if the node would accept the block, or the reason it
+    /// would not, e.g. "bad-txnmrklroot", or "inconclusive" if the block does
+    /// not build on the tip.
+    pub async fn propose_block(&self, block: &bitcoin::Block) -> Result<Option<String>> {
+        #[derive(Serialize)]
+        struct Argument {
+            mode: &'static str,
+            data: String,
+        }
+
+        self.call(
+            "getblocktemplate",
+            &[into_json(Argument {
+                mode: "proposal",
+                data: encode::serialize_hex(block),
+            })?],
+        )
+        .await
+    }
+
     /// Returns a data structure containing various state info regarding
     /// blockchain processing.
     pub async fn get_blockchain_info(&self) -> Result<json::GetBlockchainInfoResult> {
-        let mut raw: serde_json::Value = self.call("getblockchaininfo", &[]).await?;
-        // The softfork fields are not backwards compatible:
-        // - 0.18.x returns a "softforks" array and a "bip9_softforks" map.
-        // - 0.19.x returns a "softforks" map.
-        Ok(if self.version().await? < 190000 {
-            use bitcoincore_rpc::Error::UnexpectedStructure as err;
-
-            // First, remove both incompatible softfork fields.
-            // We need to scope the mutable ref here for v1.29 borrowck.
-            let (bip9_softforks, old_softforks) = {
-                let map = raw.as_object_mut().ok_or(err)?;
-                let bip9_softforks = map.remove("bip9_softforks").ok_or(err)?;
-                let old_softforks = map.remove("softforks").ok_or(err)?;
-                // Put back an empty "softforks" field.
-                map.insert("softforks".into(), serde_json::Map::new().into());
-                (bip9_softforks, old_softforks)
-            };
-            let mut ret: json::GetBlockchainInfoResult = serde_json::from_value(raw)?;
-
-            // Then convert both softfork types and add them.
-            for sf in old_softforks.as_array().ok_or(err)?.iter() {
-                let json = sf.as_object().ok_or(err)?;
-                let id = json.get("id").ok_or(err)?.as_str().ok_or(err)?;
-                let reject = json.get("reject").ok_or(err)?.as_object().ok_or(err)?;
-                let active = reject.get("status").ok_or(err)?.as_bool().ok_or(err)?;
-                ret.softforks.insert(
-                    id.into(),
-                    json::Softfork {
-                        type_: json::SoftforkType::Buried,
-                        bip9: None,
-                        height: None,
-                        active,
-                    },
-                );
-            }
-            for (id, sf) in bip9_softforks.as_object().ok_or(err)?.iter() {
-                #[derive(Deserialize)]
-                struct OldBip9SoftFork {
-                    pub status: json::Bip9SoftforkStatus,
-                    pub bit: Option<u8>,
-                    #[serde(rename = "startTime")]
-                    pub start_time: i64,
-                    pub timeout: u64,
-                    pub since: u32,
-                    pub statistics: Option<json::Bip9SoftforkStatistics>,
-                }
-                let sf: OldBip9SoftFork = serde_json::from_value(sf.clone())?;
-                ret.softforks.insert(
-                    id.clone(),
-                    json::Softfork {
-                        type_: json::SoftforkType::Bip9,
-                        bip9: Some(json::Bip9SoftforkInfo {
-                            status: sf.status,
-                            bit: sf.bit,
-                            start_time: sf.start_time,
-                            timeout: sf.timeout,
-                            since: sf.since,
-                            statistics: sf.statistics,
-                        }),
-                        height: None,
-                        active: sf.status == json::Bip9SoftforkStatus::Active,
-                    },
-                );
-            }
-            ret
-        } else {
-            serde_json::from_value(raw)?
-        })
+        self.call("getblockchaininfo", &[]).await
     }
 
     /// Returns the numbers of block in the longest chain.
@@ -302,7 +624,12 @@ impl Client {
 
     /// Get block hash at a given height
     pub async fn get_block_hash(&self, height: u64) -> Result<bitcoin::BlockHash> {
-        self.call("getblockhash", &[height.into()]).await
+        let hash = self.call("getblockhash", &[height.into()]).await?;
+        #[cfg(feature = "store")]
+        if let Some(store) = &self.store {
+            store.run(move |s| s.set_hash_at(height, &hash)).await?;
+        }
+        Ok(hash)
     }
 
     pub async fn get_block_stats(&self, height: u64) -> Result<json::GetBlockStatsResult> {
@@ -318,15 +645,39 @@ impl Client {
             .await
     }
 
+    /// Returns transaction counts and rates over the `nblocks` blocks ending
+    /// at `blockhash`, by default the last month of blocks up to the tip.
+    pub async fn get_chain_tx_stats(
+        &self,
+        nblocks: Option<u32>,
+        blockhash: Option<&bitcoin::BlockHash>,
+    ) -> Result<types::GetChainTxStatsResult> {
+        let args = [opt_into_json(nblocks)?, opt_into_json(blockhash)?];
+        self.call("getchaintxstats", trim_nulls(&args)).await
+    }
+
+    /// Returns `block_hash`, or else the block of `txid` in the transaction
+    /// index, if any.
+    fn tx_block_hash(
+        &self,
+        txid: &bitcoin::Txid,
+        block_hash: Option<&bitcoin::BlockHash>,
+    ) -> Option<bitcoin::BlockHash> {
+        block_hash
+            .copied()
+            .or_else(|| Some(self.tx_index.as_ref()?.location(txid)?.block_hash))
+    }
+
     pub async fn get_raw_transaction(
         &self,
         txid: &bitcoin::Txid,
         block_hash: Option<&bitcoin::BlockHash>,
     ) -> Result<Transaction> {
+        let block_hash = self.tx_block_hash(txid, block_hash);
         let mut args = [
             into_json(txid)?,
             into_json(false)?,
-            opt_into_json(block_hash)?,
+            opt_into_json(block_hash.as_ref())?,
         ];
         let hex: String = self
             .call("getrawtransaction", handle_defaults(&mut args, &[null()]))
@@ -339,10 +690,11 @@ impl Client {
         txid: &bitcoin::Txid,
         block_hash: Option<&bitcoin::BlockHash>,
     ) -> Result<String> {
+        let block_hash = self.tx_block_hash(txid, block_hash);
         let mut args = [
             into_json(txid)?,
             into_json(false)?,
-            opt_into_json(block_hash)?,
+            opt_into_json(block_hash.as_ref())?,
         ];
         self.call("getrawtransaction", handle_defaults(&mut args, &[null()]))
             .await
@@ -353,10 +705,11 @@ impl Client {
         txid: &bitcoin::Txid,
         block_hash: Option<&bitcoin::BlockHash>,
     ) -> Result<json::GetRawTransactionResult> {
+        let block_hash = self.tx_block_hash(txid, block_hash);
         let mut args = [
             into_json(txid)?,
             into_json(true)?,
-            opt_into_json(block_hash)?,
+            opt_into_json(block_hash.as_ref())?,
         ];
         self.call("getrawtransaction", handle_defaults(&mut args, &[null()]))
             .await
@@ -485,6 +838,28 @@ impl Client {
         Ok(FromHex::from_hex(&hex).map_err(bitcoincore_rpc::Error::from)?)
     }
 
+    /// Verifies a proof produced by `gettxoutproof` on the node and returns
+    /// the txids it commits to.
+    pub async fn verify_tx_out_proof(&self, proof: &[u8]) -> Result<Vec<bitcoin::Txid>> {
+        self.call("verifytxoutproof", &[proof.to_lower_hex_string().into()])
+            .await
+    }
+
+    /// Verifies a proof produced by `gettxoutproof` locally and returns the
+    /// txids it commits to.
+    ///
+    /// Only the block header is requested from the node, so the proof itself
+    /// does not have to come from a trusted source. Use
+    /// [merkle::verify_merkle_block] directly to check against a header that
+    /// was validated independently.
+    pub async fn verify_tx_out_proof_local(&self, proof: &[u8]) -> Result<Vec<bitcoin::Txid>> {
+        let merkle_block = merkle::decode_tx_out_proof(proof)?;
+        let header = self
+            .get_block_header(&merkle_block.header.block_hash())
+            .await?;
+        merkle::verify_merkle_block(&merkle_block, &header)
+    }
+
     pub async fn import_public_key(
         &self,
         pubkey: &PublicKey,
@@ -585,6 +960,56 @@ impl Client {
         .await
     }
 
+    /// Imports several descriptors in a single "importdescriptors" call, so
+    /// that the wallet rescans only once.
+    pub async fn import_descriptors_batch(
+        &self,
+        requests: &[json::ImportDescriptors],
+    ) -> Result<Vec<json::ImportMultiResult>> {
+        self.call("importdescriptors", &[into_json(requests)?])
+            .await
+    }
+
+    /// Lists the descriptors of a descriptor wallet, with their private keys
+    /// if `private` is set.
+    pub async fn list_descriptors(
+        &self,
+        private: Option<bool>,
+    ) -> Result<types::ListDescriptorsResult> {
+        let mut args = [opt_into_json(private)?];
+        self.call("listdescriptors", handle_defaults(&mut args, &[null()]))
+            .await
+    }
+
+    /// Adds descriptors of `address_type` derived from an HD key of the
+    /// wallet.
+    ///
+    /// Requires Core v28 or later.
+    pub async fn create_wallet_descriptor(
+        &self,
+        address_type: json::AddressType,
+        options: Option<&types::CreateWalletDescriptorOptions>,
+    ) -> Result<types::CreateWalletDescriptorResult> {
+        let mut args = [into_json(address_type)?, opt_into_json(options)?];
+        self.call(
+            "createwalletdescriptor",
+            handle_defaults(&mut args, &[null()]),
+        )
+        .await
+    }
+
+    /// Lists the HD keys used by the wallet's descriptors.
+    ///
+    /// Requires Core v28 or later.
+    pub async fn get_hd_keys(
+        &self,
+        options: Option<&types::GetHdKeysOptions>,
+    ) -> Result<Vec<types::HdKey>> {
+        let mut args = [opt_into_json(options)?];
+        self.call("gethdkeys", handle_defaults(&mut args, &[null()]))
+            .await
+    }
+
     pub async fn set_label(&self, address: &Address, label: &str) -> Result<()> {
         self.call("setlabel", &[address.to_string().into(), label.into()])
             .await
@@ -641,6 +1066,19 @@ impl Client {
             .await
     }
 
+    /// Locks `outputs` so that the wallet does not spend them, persisting
+    /// the locks in the wallet database so that they survive restarts.
+    ///
+    /// Requires Core v23 or later.
+    pub async fn lock_unspent_persistent(&self, outputs: &[OutPoint]) -> Result<bool> {
+        let outputs: Vec<_> = outputs
+            .iter()
+            .map(|o| serde_json::to_value(JsonOutPoint::from(*o)).unwrap())
+            .collect();
+        self.call("lockunspent", &[false.into(), outputs.into(), true.into()])
+            .await
+    }
+
     /// Unlock all unspent UTXOs.
     pub async fn unlock_unspent_all(&self) -> Result<bool> {
         self.call("lockunspent", &[true.into()]).await
@@ -743,6 +1181,29 @@ impl Client {
         .await
     }
 
+    /// Bumps the fee of a wallet transaction by replacing it (BIP125).
+    pub async fn bump_fee(
+        &self,
+        txid: &bitcoin::Txid,
+        options: Option<&types::BumpFeeOptions>,
+    ) -> Result<types::BumpFeeResult> {
+        let mut args = [into_json(txid)?, opt_into_json(options)?];
+        self.call("bumpfee", handle_defaults(&mut args, &[null()]))
+            .await
+    }
+
+    /// Like [Self::bump_fee], but returns the replacement as an unsigned PSBT
+    /// instead of signing and broadcasting it.
+    pub async fn psbt_bump_fee(
+        &self,
+        txid: &bitcoin::Txid,
+        options: Option<&types::BumpFeeOptions>,
+    ) -> Result<types::PsbtBumpFeeResult> {
+        let mut args = [into_json(txid)?, opt_into_json(options)?];
+        self.call("psbtbumpfee", handle_defaults(&mut args, &[null()]))
+            .await
+    }
+
     pub async fn fund_raw_transaction<R: RawTx>(
         &self,
         tx: R,
@@ -759,7 +1220,12 @@ impl Client {
             .await
     }
 
-    #[deprecated]
+    /// Signs `tx` with `private_keys`, or with the wallet if none is given.
+    ///
+    /// "signrawtransaction" was removed in 0.18; newer servers are sent
+    /// "signrawtransactionwithkey" or "signrawtransactionwithwallet"
+    /// instead.
+    #[deprecated(note = "use sign_raw_transaction_with_wallet or sign_raw_transaction_with_key")]
     pub async fn sign_raw_transaction<R: RawTx>(
         &self,
         tx: R,
@@ -767,6 +1233,18 @@ impl Client {
         private_keys: Option<&[PrivateKey]>,
         sighash_type: Option<json::SigHashType>,
     ) -> Result<json::SignRawTransactionResult> {
+        if self.is_removed("signrawtransaction").await? {
+            return match private_keys {
+                Some(keys) => {
+                    self.sign_raw_transaction_with_key(tx, keys, utxos, sighash_type)
+                        .await
+                }
+                None => {
+                    self.sign_raw_transaction_with_wallet(tx, utxos, sighash_type)
+                        .await
+                }
+            };
+        }
         let mut args = [
             tx.raw_hex().into(),
             opt_into_json(utxos)?,
@@ -845,6 +1323,77 @@ impl Client {
         self.call("verifymessage", &args).await
     }
 
+    /// Signs `message` with the key of `address`, which must be a P2PKH
+    /// address of the wallet.
+    pub async fn sign_message(
+        &self,
+        address: &Address,
+        message: &str,
+    ) -> Result<sign_message::MessageSignature> {
+        let signature: String = self
+            .call(
+                "signmessage",
+                &[address.to_string().into(), into_json(message)?],
+            )
+            .await?;
+        Ok(message::decode_signature(&signature)?.0)
+    }
+
+    /// Signs `message` with `privkey`, without a wallet.
+    pub async fn sign_message_with_priv_key(
+        &self,
+        privkey: &PrivateKey,
+        message: &str,
+    ) -> Result<sign_message::MessageSignature> {
+        let signature: String = self
+            .call(
+                "signmessagewithprivkey",
+                &[privkey.to_wif().into(), into_json(message)?],
+            )
+            .await?;
+        Ok(message::decode_signature(&signature)?.0)
+    }
+
+    /// Lists the labels of the wallet, optionally only the ones used for
+    /// `purpose`.
+    pub async fn list_labels(
+        &self,
+        purpose: Option<json::GetAddressInfoResultLabelPurpose>,
+    ) -> Result<Vec<String>> {
+        let mut args = [opt_into_json(purpose)?];
+        self.call("listlabels", handle_defaults(&mut args, &[null()]))
+            .await
+    }
+
+    pub async fn get_addresses_by_label(
+        &self,
+        label: &str,
+    ) -> Result<HashMap<Address<NetworkUnchecked>, types::AddressesByLabelEntry>> {
+        self.call("getaddressesbylabel", &[into_json(label)?]).await
+    }
+
+    pub async fn list_received_by_label(
+        &self,
+        minconf: Option<u32>,
+        include_empty: Option<bool>,
+        include_watchonly: Option<bool>,
+    ) -> Result<Vec<types::ListReceivedByLabelResult>> {
+        let mut args = [
+            opt_into_json(minconf)?,
+            opt_into_json(include_empty)?,
+            opt_into_json(include_watchonly)?,
+        ];
+        let defaults = [1.into(), false.into(), null()];
+        self.call("listreceivedbylabel", handle_defaults(&mut args, &defaults))
+            .await
+    }
+
+    /// Lists groups of addresses whose common ownership was made public by
+    /// spending them together or as change.
+    pub async fn list_address_groupings(&self) -> Result<Vec<Vec<types::AddressGrouping>>> {
+        self.call("listaddressgroupings", &[]).await
+    }
+
     /// Generate new address under own control
     pub async fn get_new_address(
         &self,
@@ -889,15 +1438,76 @@ impl Client {
 
     /// Mine up to block_num blocks immediately (before the RPC call returns)
     /// to an address in the wallet.
+    ///
+    /// "generate" was removed in 0.19; newer servers mine to a new wallet
+    /// address with "generatetoaddress" instead.
     pub async fn generate(
         &self,
         block_num: u64,
         maxtries: Option<u64>,
     ) -> Result<Vec<bitcoin::BlockHash>> {
-        self.call("generate", &[block_num.into(), opt_into_json(maxtries)?])
+        let args = [block_num.into(), opt_into_json(maxtries)?];
+        if !self.is_removed("generate").await? {
+            return self.call("generate", trim_nulls(&args)).await;
+        }
+        let address = self.get_new_address(None, None).await?.assume_checked();
+        let args = [
+            block_num.into(),
+            address.to_string().into(),
+            opt_into_json(maxtries)?,
+        ];
+        self.call("generatetoaddress", trim_nulls(&args)).await
+    }
+
+    /// Mine up to block_num blocks immediately (before the RPC call returns)
+    /// to the output script of `descriptor`.
+    pub async fn generate_to_descriptor(
+        &self,
+        block_num: u64,
+        descriptor: &str,
+        maxtries: Option<u64>,
+    ) -> Result<Vec<bitcoin::BlockHash>> {
+        let args = [
+            block_num.into(),
+            into_json(descriptor)?,
+            opt_into_json(maxtries)?,
+        ];
+        self.call("generatetodescriptor", trim_nulls(&args)).await
+    }
+
+    /// Mines a block with exactly `transactions`, in that order, paying the
+    /// coinbase to `output`, an address or a descriptor.
+    ///
+    /// With `submit` false, the block is returned in the result instead of
+    /// being submitted (since 25.0).
+    pub async fn generate_block(
+        &self,
+        output: &str,
+        transactions: &[types::GenerateBlockTx],
+        submit: Option<bool>,
+    ) -> Result<types::GenerateBlockResult> {
+        let args = [
+            into_json(output)?,
+            into_json(transactions)?,
+            opt_into_json(submit)?,
+        ];
+        self.call("generateblock", trim_nulls(&args)).await
+    }
+
+    /// Submits a block header, failing if it is invalid or does not connect
+    /// to a known block.
+    pub async fn submit_header(&self, header: &bitcoin::block::Header) -> Result<()> {
+        self.call("submitheader", &[encode::serialize_hex(header).into()])
             .await
     }
 
+    /// Sets the node clock to `timestamp`, or back to the system clock with 0.
+    ///
+    /// Only available on regtest.
+    pub async fn set_mock_time(&self, timestamp: u64) -> Result<()> {
+        self.call("setmocktime", &[timestamp.into()]).await
+    }
+
     /// Mark a block as invalid by `block_hash`
     pub async fn invalidate_block(&self, block_hash: &bitcoin::BlockHash) -> Result<()> {
         self.call("invalidateblock", &[into_json(block_hash)?])
@@ -935,6 +1545,19 @@ impl Client {
         self.call("getmempoolentry", &[into_json(txid)?]).await
     }
 
+    /// Returns the mempool transactions spending the given outputs.
+    /// Added in Core v24.
+    pub async fn get_tx_spending_prevout(
+        &self,
+        outputs: &[OutPoint],
+    ) -> Result<Vec<types::TxSpendingPrevOutResult>> {
+        let outputs: Vec<_> = outputs
+            .iter()
+            .map(|o| serde_json::to_value(JsonOutPoint::from(*o)).unwrap())
+            .collect();
+        self.call("gettxspendingprevout", &[outputs.into()]).await
+    }
+
     /// Get information about all known tips in the block tree, including the
     /// main chain as well as stale branches.
     pub async fn get_chain_tips(&self) -> Result<json::GetChainTipsResult> {
@@ -980,6 +1603,28 @@ impl Client {
         .await
     }
 
+    /// Pays several addresses in a single transaction.
+    pub async fn send_many(&self, request: &types::SendManyRequest) -> Result<bitcoin::Txid> {
+        let args = send_many_args(request)?;
+        self.call("sendmany", trim_nulls(&args)).await
+    }
+
+    /// Creates, signs and by default broadcasts a transaction with "send".
+    ///
+    /// Requires Core v21 or later.
+    pub async fn send(&self, request: &types::SendRequest) -> Result<types::SendResult> {
+        let args = send_args(into_json(&request.outputs)?, &request.options)?;
+        self.call("send", trim_nulls(&args)).await
+    }
+
+    /// Spends the wallet's funds, or the given inputs, without change.
+    ///
+    /// Requires Core v24 or later.
+    pub async fn send_all(&self, request: &types::SendAllRequest) -> Result<types::SendResult> {
+        let args = send_args(request.recipients_json().into(), &request.options)?;
+        self.call("sendall", trim_nulls(&args)).await
+    }
+
     /// Attempts to add a node to the addnode list.
     /// Nodes added using addnode (or -connect) are protected from DoS disconnection and are not required to be full nodes/support SegWit as other outbound peers are (though such peers will not be synced from).
     pub async fn add_node(&self, addr: &str) -> Result<()> {
@@ -1100,6 +1745,25 @@ impl Client {
             .await
     }
 
+    /// Returns the raw fee estimates of the short, medium and long horizons
+    /// for a confirmation target.
+    ///
+    /// # Arguments
+    ///
+    /// 1. `conf_target`: Confirmation target in blocks.
+    /// 2. `threshold`: The proportion of transactions in a fee bucket that must
+    ///    have confirmed within the target for the bucket to pass. Defaults
+    ///    to 0.95.
+    pub async fn estimate_raw_fee(
+        &self,
+        conf_target: u16,
+        threshold: Option<f64>,
+    ) -> Result<types::EstimateRawFeeResult> {
+        let mut args = [into_json(conf_target)?, opt_into_json(threshold)?];
+        self.call("estimaterawfee", handle_defaults(&mut args, &[null()]))
+            .await
+    }
+
     /// Waits for a specific new block and returns useful info about it.
     /// Returns the current block on timeout or exit.
     ///
@@ -1107,6 +1771,7 @@ impl Client {
     ///
     /// 1. `timeout`: Time in milliseconds to wait for a response. 0
     /// indicates no timeout.
+    #[allow(clippy::doc_lazy_continuation)]
     pub async fn wait_for_new_block(&self, timeout: u64) -> Result<json::BlockRef> {
         self.call("waitfornewblock", &[into_json(timeout)?]).await
     }
@@ -1119,6 +1784,7 @@ impl Client {
     /// 1. `blockhash`: Block hash to wait for.
     /// 2. `timeout`: Time in milliseconds to wait for a response. 0
     /// indicates no timeout.
+    #[allow(clippy::doc_lazy_continuation)]
     pub async fn wait_for_block(
         &self,
         blockhash: &bitcoin::BlockHash,
@@ -1220,6 +1886,57 @@ impl Client {
             .await
     }
 
+    /// Marks an unconfirmed wallet transaction that is not in the mempool
+    /// as abandoned, so that its inputs can be spent again.
+    pub async fn abandon_transaction(&self, txid: &bitcoin::Txid) -> Result<()> {
+        self.call("abandontransaction", &[into_json(txid)?]).await
+    }
+
+    /// Stops the wallet rescan in progress, if any. Returns `false` if there
+    /// was none.
+    pub async fn abort_rescan(&self) -> Result<bool> {
+        self.call("abortrescan", &[]).await
+    }
+
+    /// Progress of the wallet rescan in progress, if any.
+    pub async fn get_rescan_progress(&self) -> Result<Option<types::RescanProgress>> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Scanning {
+            Scanning(types::RescanProgress),
+            NotScanning(serde::de::IgnoredAny),
+        }
+        #[derive(Deserialize)]
+        struct Response {
+            scanning: Option<Scanning>,
+        }
+        let res: Response = self.call("getwalletinfo", &[]).await?;
+        Ok(match res.scanning {
+            Some(Scanning::Scanning(progress)) => Some(progress),
+            _ => None,
+        })
+    }
+
+    /// Imports a transaction paying to the wallet without a rescan, for
+    /// pruned nodes. `tx_out_proof` is the proof from `gettxoutproof` that
+    /// the transaction is in a block.
+    pub async fn import_pruned_funds<R: RawTx>(&self, tx: R, tx_out_proof: &[u8]) -> Result<()> {
+        self.call(
+            "importprunedfunds",
+            &[
+                tx.raw_hex().into(),
+                tx_out_proof.to_lower_hex_string().into(),
+            ],
+        )
+        .await
+    }
+
+    /// Removes a transaction imported with [Self::import_pruned_funds] from
+    /// the wallet.
+    pub async fn remove_pruned_funds(&self, txid: &bitcoin::Txid) -> Result<()> {
+        self.call("removeprunedfunds", &[into_json(txid)?]).await
+    }
+
     pub async fn rescan_blockchain(
         &self,
         start_from: Option<usize>,
@@ -1286,6 +2003,98 @@ impl Client {
         self.call("uptime", &[]).await
     }
 
+    /// Returns the commands the server is running and the path of its debug
+    /// log.
+    pub async fn get_rpc_info(&self) -> Result<types::GetRpcInfoResult> {
+        self.call("getrpcinfo", &[]).await
+    }
+
+    /// Returns statistics about the secure memory pool.
+    pub async fn get_memory_info(&self) -> Result<types::GetMemoryInfoResult> {
+        self.call("getmemoryinfo", &[]).await
+    }
+
+    /// Returns the XML report of the allocator, only available when the node
+    /// is built with glibc.
+    pub async fn get_memory_info_malloc(&self) -> Result<String> {
+        self.call("getmemoryinfo", &["mallocinfo".into()]).await
+    }
+
+    /// Enables the debug log categories in `include` and disables those in
+    /// `exclude`, then returns whether each category is enabled.
+    ///
+    /// Pass empty slices to only read the current state; "all" and "none"
+    /// stand for every category.
+    pub async fn logging(
+        &self,
+        include: &[&str],
+        exclude: &[&str],
+    ) -> Result<HashMap<String, bool>> {
+        self.call("logging", &[into_json(include)?, into_json(exclude)?])
+            .await
+    }
+
+    /// Returns the state of the soft fork deployments at `blockhash`, or at
+    /// the tip.
+    pub async fn get_deployment_info(
+        &self,
+        blockhash: Option<&bitcoin::BlockHash>,
+    ) -> Result<types::GetDeploymentInfoResult> {
+        let args = [opt_into_json(blockhash)?];
+        self.call("getdeploymentinfo", trim_nulls(&args)).await
+    }
+
+    /// Returns the chainstates of the node: one, or two while a UTXO
+    /// snapshot is being validated in the background.
+    pub async fn get_chain_states(&self) -> Result<types::GetChainStatesResult> {
+        self.call("getchainstates", &[]).await
+    }
+
+    /// Writes the UTXO set at the tip to `path`, or at height `rollback` by
+    /// rolling the chain back temporarily (since 28.0).
+    ///
+    /// The dump can take several minutes; the call waits for it since no
+    /// request timeout applies. `path` is relative to the node data
+    /// directory and must not exist yet.
+    pub async fn dump_tx_out_set(
+        &self,
+        path: &str,
+        rollback: Option<u64>,
+    ) -> Result<types::DumpTxOutSetResult> {
+        match rollback {
+            None => self.call("dumptxoutset", &[into_json(path)?]).await,
+            Some(height) => {
+                let options = serde_json::json!({ "rollback": height });
+                self.call(
+                    "dumptxoutset",
+                    &[into_json(path)?, "rollback".into(), options],
+                )
+                .await
+            }
+        }
+    }
+
+    /// Loads the UTXO snapshot at `path` into a new chainstate, which the
+    /// node then syncs from while validating the snapshot in the background.
+    ///
+    /// The snapshot base block must match the assumeutxo parameters of the
+    /// node, and its header must be known. Like [Self::dump_tx_out_set], the
+    /// call waits for the load to complete.
+    pub async fn load_tx_out_set(&self, path: &str) -> Result<types::LoadTxOutSetResult> {
+        self.call("loadtxoutset", &[into_json(path)?]).await
+    }
+
+    /// Returns the help text of `command`, or the list of all commands.
+    pub async fn help(&self, command: Option<&str>) -> Result<String> {
+        let args = [opt_into_json(command)?];
+        self.call("help", trim_nulls(&args)).await
+    }
+
+    /// Returns the commands the server supports, parsed from `help`.
+    pub async fn help_index(&self) -> Result<HelpIndex> {
+        Ok(HelpIndex::parse(&self.help(None).await?))
+    }
+
     /// Submit a block
     pub async fn submit_block(&self, block: &bitcoin::Block) -> Result<()> {
         let block_hex: String = bitcoin::consensus::encode::serialize_hex(block);
@@ -1314,6 +2123,55 @@ impl Client {
         self.call("scantxoutset", &["start".into(), into_json(descriptors)?])
             .await
     }
+
+    /// Returns the progress of the running `scantxoutset` scan, or `None` if
+    /// no scan is in progress.
+    pub async fn scan_tx_out_set_status(&self) -> Result<Option<types::ScanTxOutStatus>> {
+        opt_result(self.call("scantxoutset", &["status".into()]).await?)
+    }
+
+    /// Aborts the running `scantxoutset` scan.
+    ///
+    /// Returns `false` if there was no scan to abort.
+    pub async fn scan_tx_out_set_abort(&self) -> Result<bool> {
+        self.call("scantxoutset", &["abort".into()]).await
+    }
+}
+
+/// The arguments of "sendmany", with nulls for the unset ones.
+fn send_many_args(request: &types::SendManyRequest) -> Result<[serde_json::Value; 9]> {
+    let amounts: serde_json::Map<String, serde_json::Value> = request
+        .amounts
+        .iter()
+        .map(|(address, amount)| (address.to_string(), amount.to_btc().into()))
+        .collect();
+    let subtract_fee_from: Vec<String> = request
+        .subtract_fee_from
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    let fee_rate = request
+        .fee_rate
+        .map(|rate| rate.to_sat_per_kwu() as f64 / 250.0);
+    Ok([
+        "".into(),
+        amounts.into(),
+        opt_into_json(request.min_conf)?,
+        opt_into_json(request.comment.as_deref())?,
+        into_json(subtract_fee_from)?,
+        opt_into_json(request.replaceable)?,
+        opt_into_json(request.conf_target)?,
+        opt_into_json(request.estimate_mode)?,
+        opt_into_json(fee_rate)?,
+    ])
+}
+
+/// The arguments of "send" and "sendall", passing the options by name.
+fn send_args(
+    outputs: serde_json::Value,
+    options: &types::SendOptions,
+) -> Result<[serde_json::Value; 5]> {
+    Ok([outputs, null(), null(), null(), into_json(options)?])
 }
 
 /// Shorthand for converting a variable into a serde_json::Value.
@@ -1398,6 +2256,13 @@ fn handle_defaults<'a>(
     }
 }
 
+/// Drops trailing null arguments, for RPCs whose optional arguments may be
+/// null but have no default that can be passed instead.
+fn trim_nulls(args: &[serde_json::Value]) -> &[serde_json::Value] {
+    let len = args.iter().rposition(|a| !a.is_null()).map_or(0, |i| i + 1);
+    &args[..len]
+}
+
 /// Convert a possible-null result into an Option.
 fn opt_result<T: for<'a> serde::de::Deserialize<'a>>(
     result: serde_json::Value,
@@ -1471,6 +2336,19 @@ mod tests {
         test_handle_defaults_inner().unwrap();
     }
 
+    #[test]
+    fn test_trim_nulls() {
+        let args = [
+            into_json(0).unwrap(),
+            null(),
+            into_json(1).unwrap(),
+            null(),
+            null(),
+        ];
+        assert_eq!(trim_nulls(&args), &args[..3]);
+        assert!(trim_nulls(&[null(), null()]).is_empty());
+    }
+
     #[test]
     fn auth_cookie_file_ignores_newline() {
         let tempdir = tempfile::tempdir().unwrap();
@@ -1503,4 +2381,94 @@ mod tests {
             Err(bitcoincore_rpc::Error::InvalidCookieFile)
         ));
     }
+
+    const ADDRESS: &str = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq";
+
+    fn address() -> Address {
+        ADDRESS
+            .parse::<Address<NetworkUnchecked>>()
+            .unwrap()
+            .assume_checked()
+    }
+
+    #[test]
+    fn send_many_arguments() {
+        let request = types::SendManyRequest::new()
+            .recipient(address(), Amount::from_sat(150_000))
+            .subtract_fee_from(address())
+            .fee_rate(bitcoin::FeeRate::from_sat_per_vb(10).unwrap());
+        let args = send_many_args(&request).unwrap();
+        assert_eq!(
+            trim_nulls(&args),
+            [
+                "".into(),
+                serde_json::json!({ ADDRESS: 0.0015 }),
+                null(),
+                null(),
+                serde_json::json!([ADDRESS]),
+                null(),
+                null(),
+                null(),
+                10.0.into(),
+            ]
+        );
+
+        let request = types::SendManyRequest::new().recipient(address(), Amount::ONE_BTC);
+        let args = send_many_args(&request).unwrap();
+        assert_eq!(trim_nulls(&args).len(), 5);
+    }
+
+    #[test]
+    fn send_and_send_all_arguments() {
+        let request = types::SendRequest::new()
+            .recipient(address(), Amount::from_sat(50_000))
+            .data(vec![0xde, 0xad])
+            .subtract_fee_from_output(0)
+            .fee_rate(bitcoin::FeeRate::from_sat_per_vb(2).unwrap())
+            .replaceable(true);
+        let args = send_args(into_json(&request.outputs).unwrap(), &request.options).unwrap();
+        assert_eq!(
+            trim_nulls(&args),
+            [
+                serde_json::json!([{ ADDRESS: 0.0005 }, { "data": "dead" }]),
+                null(),
+                null(),
+                null(),
+                serde_json::json!({
+                    "fee_rate": 2.0,
+                    "replaceable": true,
+                    "subtract_fee_from_outputs": [0],
+                }),
+            ]
+        );
+
+        let request = types::SendAllRequest::new()
+            .recipient(address(), Amount::from_sat(50_000))
+            .remainder_to(address())
+            .send_max(true)
+            .min_conf(1);
+        let args = send_args(request.recipients_json().into(), &request.options).unwrap();
+        assert_eq!(
+            trim_nulls(&args),
+            [
+                serde_json::json!([{ ADDRESS: 0.0005 }, ADDRESS]),
+                null(),
+                null(),
+                null(),
+                serde_json::json!({ "send_max": true, "minconf": 1 }),
+            ]
+        );
+
+        let args = send_args(serde_json::json!([]), &types::SendOptions::default()).unwrap();
+        assert_eq!(
+            trim_nulls(&args),
+            [
+                serde_json::json!([]),
+                null(),
+                null(),
+                null(),
+                serde_json::json!({})
+            ]
+        );
+    }
 }
@@ -0,0 +1,559 @@
+use std::collections::BTreeMap;
+
+use bitcoincore_rpc_json::{
+    bitcoin::{Amount, FeeRate, OutPoint, Script, ScriptBuf, TxOut, Weight},
+    ListUnspentResultEntry,
+};
+
+/// Weight of an input without its scriptSig and witness: outpoint, sequence
+/// and the scriptSig length.
+pub const TXIN_BASE_WEIGHT: Weight = Weight::from_wu(41 * 4);
+
+/// Default change target of [Knapsack], one hundredth of a bitcoin.
+pub const DEFAULT_MIN_CHANGE: Amount = Amount::from_sat(1_000_000);
+
+/// Weight of the scriptSig and witness needed to spend an output of the given
+/// type, or `None` for types whose satisfaction size is not known up front.
+///
+/// P2SH outputs are assumed to wrap P2WPKH. Signatures are counted at their
+/// maximum size of 72 bytes.
+pub fn satisfaction_weight(script_pubkey: &Script) -> Option<Weight> {
+    if script_pubkey.is_p2wpkh() {
+        // Witness item count, signature and compressed key.
+        Some(Weight::from_witness_data_size(1 + 73 + 34))
+    } else if script_pubkey.is_p2tr() {
+        // Key path spend with a 64-byte Schnorr signature.
+        Some(Weight::from_witness_data_size(1 + 65))
+    } else if script_pubkey.is_p2sh() {
+        // Push of the P2WPKH redeem script, and the P2WPKH witness.
+        Some(Weight::from_non_witness_data_size(23) + Weight::from_witness_data_size(1 + 73 + 34))
+    } else if script_pubkey.is_p2pkh() {
+        Some(Weight::from_non_witness_data_size(73 + 34))
+    } else {
+        None
+    }
+}
+
+/// Fee for `weight` at `fee_rate`, rounded up to the next satoshi.
+pub fn fee_for(fee_rate: FeeRate, weight: Weight) -> Amount {
+    Amount::from_sat((fee_rate.to_sat_per_kwu() * weight.to_wu()).div_ceil(1000))
+}
+
+/// An output that may be spent by a new transaction.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Candidate {
+    pub outpoint: OutPoint,
+    pub txout: TxOut,
+    /// Weight of the scriptSig and witness spending the output.
+    pub satisfaction_weight: Weight,
+    pub confirmations: u32,
+}
+
+impl Candidate {
+    /// Turns a `listunspent` entry into a candidate.
+    ///
+    /// Returns `None` for outputs the wallet cannot spend, or whose spending
+    /// weight cannot be estimated.
+    pub fn from_unspent(entry: &ListUnspentResultEntry) -> Option<Self> {
+        if !entry.spendable && !entry.solvable {
+            return None;
+        }
+        Some(Self {
+            outpoint: OutPoint::new(entry.txid, entry.vout),
+            satisfaction_weight: satisfaction_weight(&entry.script_pub_key)?,
+            txout: TxOut {
+                value: entry.amount,
+                script_pubkey: entry.script_pub_key.clone(),
+            },
+            confirmations: entry.confirmations,
+        })
+    }
+
+    /// Weight the candidate adds to a transaction spending it.
+    pub fn weight(&self) -> Weight {
+        TXIN_BASE_WEIGHT + self.satisfaction_weight
+    }
+
+    /// Whether spending the candidate needs a witness.
+    pub fn is_segwit(&self) -> bool {
+        !self.txout.script_pubkey.is_p2pkh()
+    }
+
+    /// Fee for spending the candidate at `fee_rate`.
+    pub fn fee(&self, fee_rate: FeeRate) -> Amount {
+        fee_for(fee_rate, self.weight())
+    }
+
+    /// Value of the candidate minus the fee for spending it, in satoshis.
+    pub fn effective_value(&self, fee_rate: FeeRate) -> i64 {
+        self.txout.value.to_sat() as i64 - self.fee(fee_rate).to_sat() as i64
+    }
+}
+
+/// What a coin selection has to cover.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SelectionTarget {
+    /// Amount the effective values of the selected candidates must add up
+    /// to: the outputs and the fee for the rest of the transaction.
+    pub amount: Amount,
+    pub fee_rate: FeeRate,
+    /// Fee rate expected when spending outputs later, used to weigh the cost
+    /// of spending more inputs now.
+    pub long_term_fee_rate: FeeRate,
+    /// Cost of creating a change output and spending it later. Selections
+    /// exceeding the target by less than this need no change.
+    pub cost_of_change: Amount,
+}
+
+/// A coin selection algorithm.
+///
+/// Algorithms must be deterministic: the same candidates and target always
+/// give the same selection.
+pub trait CoinSelection: Send + Sync {
+    /// Name of the algorithm, recorded with the selection.
+    fn name(&self) -> &'static str;
+
+    /// Returns the indices of the selected candidates, or `None` if the
+    /// algorithm finds no selection reaching the target.
+    fn select(&self, candidates: &[Candidate], target: &SelectionTarget) -> Option<Vec<usize>>;
+}
+
+/// Indices of candidates with a positive effective value, largest first.
+///
+/// Ties are broken by outpoint to keep the order deterministic.
+fn by_effective_value(candidates: &[Candidate], fee_rate: FeeRate) -> Vec<(usize, i64)> {
+    let mut pool: Vec<(usize, i64)> = candidates
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (i, c.effective_value(fee_rate)))
+        .filter(|(_, value)| *value > 0)
+        .collect();
+    pool.sort_by(|(a, va), (b, vb)| {
+        vb.cmp(va)
+            .then_with(|| candidates[*a].outpoint.cmp(&candidates[*b].outpoint))
+    });
+    pool
+}
+
+/// Branch and bound search for a selection that needs no change output,
+/// minimizing waste, as done by Bitcoin Core.
+#[derive(Clone, Copy, Debug)]
+pub struct BranchAndBound {
+    pub max_tries: usize,
+}
+
+impl Default for BranchAndBound {
+    fn default() -> Self {
+        Self { max_tries: 100_000 }
+    }
+}
+
+impl CoinSelection for BranchAndBound {
+    fn name(&self) -> &'static str {
+        "branch-and-bound"
+    }
+
+    fn select(&self, candidates: &[Candidate], target: &SelectionTarget) -> Option<Vec<usize>> {
+        let pool = by_effective_value(candidates, target.fee_rate);
+        // Spending an input now rather than later costs the difference in fee.
+        let waste: Vec<i64> = pool
+            .iter()
+            .map(|(i, _)| {
+                let c = &candidates[*i];
+                c.fee(target.fee_rate).to_sat() as i64
+                    - c.fee(target.long_term_fee_rate).to_sat() as i64
+            })
+            .collect();
+        let amount = target.amount.to_sat() as i64;
+        let upper = amount + target.cost_of_change.to_sat() as i64;
+        let high_fee_rate = target.fee_rate > target.long_term_fee_rate;
+
+        let mut available: i64 = pool.iter().map(|(_, v)| v).sum();
+        if available < amount {
+            return None;
+        }
+        let mut value = 0;
+        let mut curr_waste = 0;
+        let mut selection: Vec<usize> = Vec::new();
+        let mut best: Option<Vec<usize>> = None;
+        let mut best_waste = i64::MAX;
+
+        let mut index = 0;
+        for _ in 0..self.max_tries {
+            let mut backtrack = false;
+            if value + available < amount
+                || value > upper
+                || (curr_waste > best_waste && high_fee_rate)
+            {
+                backtrack = true;
+            } else if value >= amount {
+                // Excess beyond the target is lost to fees.
+                if curr_waste + (value - amount) <= best_waste {
+                    best_waste = curr_waste + (value - amount);
+                    best = Some(selection.clone());
+                }
+                backtrack = true;
+            }
+
+            if backtrack {
+                let Some(&last) = selection.last() else {
+                    break;
+                };
+                // Put the omitted candidates back before trying to omit the
+                // last included one.
+                while index > last + 1 {
+                    index -= 1;
+                    available += pool[index].1;
+                }
+                value -= pool[last].1;
+                curr_waste -= waste[last];
+                selection.pop();
+                index = last + 1;
+            } else {
+                let (_, candidate_value) = pool[index];
+                available -= candidate_value;
+                // Skip candidates equivalent to an omitted predecessor, they
+                // would only lead to duplicate branches.
+                let duplicate = !selection.is_empty()
+                    && selection.last() != Some(&(index - 1))
+                    && pool[index - 1].1 == candidate_value
+                    && waste[index - 1] == waste[index];
+                if !duplicate {
+                    selection.push(index);
+                    value += candidate_value;
+                    curr_waste += waste[index];
+                }
+                index += 1;
+            }
+        }
+
+        best.map(|selection| selection.into_iter().map(|i| pool[i].0).collect())
+    }
+}
+
+/// Bitcoin Core's knapsack solver: approximates the smallest subset that
+/// covers the target plus a change of at least `min_change`.
+///
+/// Random choices are drawn from a generator seeded with `seed`, so a given
+/// seed always produces the same selection.
+#[derive(Clone, Copy, Debug)]
+pub struct Knapsack {
+    pub seed: u64,
+    pub iterations: usize,
+    pub min_change: Amount,
+}
+
+impl Default for Knapsack {
+    fn default() -> Self {
+        Self {
+            seed: 0x5eed,
+            iterations: 1000,
+            min_change: DEFAULT_MIN_CHANGE,
+        }
+    }
+}
+
+/// xorshift64*, enough to explore subsets reproducibly.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.max(1))
+    }
+
+    fn next_bool(&mut self) -> bool {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 63) == 1
+    }
+}
+
+impl Knapsack {
+    fn approximate_best_subset(
+        &self,
+        rng: &mut Rng,
+        values: &[i64],
+        total: i64,
+        target: i64,
+    ) -> (Vec<bool>, i64) {
+        let mut best = vec![true; values.len()];
+        let mut best_value = total;
+        for _ in 0..self.iterations {
+            if best_value == target {
+                break;
+            }
+            let mut included = vec![false; values.len()];
+            let mut sum = 0;
+            let mut reached = false;
+            for pass in 0..2 {
+                if reached {
+                    break;
+                }
+                for (i, value) in values.iter().enumerate() {
+                    // The first pass picks at random, the second fills up.
+                    let pick = if pass == 0 {
+                        rng.next_bool()
+                    } else {
+                        !included[i]
+                    };
+                    if !pick {
+                        continue;
+                    }
+                    sum += value;
+                    included[i] = true;
+                    if sum >= target {
+                        reached = true;
+                        if sum < best_value {
+                            best_value = sum;
+                            best = included.clone();
+                        }
+                        sum -= value;
+                        included[i] = false;
+                    }
+                }
+            }
+        }
+        (best, best_value)
+    }
+}
+
+impl CoinSelection for Knapsack {
+    fn name(&self) -> &'static str {
+        "knapsack"
+    }
+
+    fn select(&self, candidates: &[Candidate], target: &SelectionTarget) -> Option<Vec<usize>> {
+        let amount = target.amount.to_sat() as i64;
+        let min_change = self.min_change.to_sat() as i64;
+
+        let mut lower = Vec::new();
+        let mut lowest_larger: Option<(usize, i64)> = None;
+        for (i, value) in by_effective_value(candidates, target.fee_rate) {
+            if value == amount {
+                return Some(vec![i]);
+            } else if value < amount + min_change {
+                lower.push((i, value));
+            } else if lowest_larger.map_or(true, |(_, v)| value <= v) {
+                lowest_larger = Some((i, value));
+            }
+        }
+
+        let total_lower: i64 = lower.iter().map(|(_, v)| v).sum();
+        if total_lower == amount {
+            return Some(lower.into_iter().map(|(i, _)| i).collect());
+        }
+        if total_lower < amount {
+            return lowest_larger.map(|(i, _)| vec![i]);
+        }
+
+        let values: Vec<i64> = lower.iter().map(|(_, v)| *v).collect();
+        let mut rng = Rng::new(self.seed);
+        let (mut best, mut best_value) =
+            self.approximate_best_subset(&mut rng, &values, total_lower, amount);
+        if best_value != amount && total_lower >= amount + min_change {
+            (best, best_value) =
+                self.approximate_best_subset(&mut rng, &values, total_lower, amount + min_change);
+        }
+
+        match lowest_larger {
+            Some((i, larger))
+                if (best_value != amount && best_value < amount + min_change)
+                    || larger <= best_value =>
+            {
+                Some(vec![i])
+            }
+            _ => Some(
+                lower
+                    .iter()
+                    .zip(best)
+                    .filter(|(_, included)| *included)
+                    .map(|((i, _), _)| *i)
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// Selects the largest candidates until the target is reached.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct LargestFirst;
+
+impl CoinSelection for LargestFirst {
+    fn name(&self) -> &'static str {
+        "largest-first"
+    }
+
+    fn select(&self, candidates: &[Candidate], target: &SelectionTarget) -> Option<Vec<usize>> {
+        let amount = target.amount.to_sat() as i64;
+        let mut value = 0;
+        let mut selection = Vec::new();
+        for (i, candidate_value) in by_effective_value(candidates, target.fee_rate) {
+            if value >= amount {
+                break;
+            }
+            value += candidate_value;
+            selection.push(i);
+        }
+        (value >= amount).then_some(selection)
+    }
+}
+
+/// Spends every output of a script together, so that a transaction never
+/// reveals part of an address' funds while leaving the rest for later to be
+/// linked to it.
+///
+/// The address with the smallest balance covering the target is preferred,
+/// so that a single address is spent. Otherwise whole addresses are added,
+/// largest first.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Privacy;
+
+impl CoinSelection for Privacy {
+    fn name(&self) -> &'static str {
+        "privacy"
+    }
+
+    fn select(&self, candidates: &[Candidate], target: &SelectionTarget) -> Option<Vec<usize>> {
+        let amount = target.amount.to_sat() as i64;
+        let mut groups: BTreeMap<&ScriptBuf, (i64, Vec<usize>)> = BTreeMap::new();
+        for (i, candidate) in candidates.iter().enumerate() {
+            let group = groups.entry(&candidate.txout.script_pubkey).or_default();
+            group.0 += candidate.effective_value(target.fee_rate);
+            group.1.push(i);
+        }
+        let mut groups: Vec<(i64, Vec<usize>)> = groups
+            .into_values()
+            .filter(|(value, _)| *value > 0)
+            .collect();
+
+        if let Some((_, selection)) = groups
+            .iter()
+            .filter(|(value, _)| *value >= amount)
+            .min_by_key(|(value, _)| *value)
+        {
+            return Some(selection.clone());
+        }
+
+        groups.sort_by(|(a, _), (b, _)| b.cmp(a));
+        let mut value = 0;
+        let mut selection = Vec::new();
+        for (group_value, indices) in groups {
+            if value >= amount {
+                break;
+            }
+            value += group_value;
+            selection.extend(indices);
+        }
+        (value >= amount).then_some(selection)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc_json::bitcoin::{hashes::Hash, Txid};
+
+    fn p2wpkh(tag: u8) -> ScriptBuf {
+        let mut bytes = vec![0x00, 0x14];
+        bytes.extend([tag; 20]);
+        ScriptBuf::from_bytes(bytes)
+    }
+
+    fn candidates(values: &[(u64, u8)]) -> Vec<Candidate> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, (sats, tag))| Candidate {
+                outpoint: OutPoint::new(Txid::all_zeros(), i as u32),
+                txout: TxOut {
+                    value: Amount::from_sat(*sats),
+                    script_pubkey: p2wpkh(*tag),
+                },
+                satisfaction_weight: satisfaction_weight(&p2wpkh(*tag)).unwrap(),
+                confirmations: 1,
+            })
+            .collect()
+    }
+
+    fn target(amount: u64) -> SelectionTarget {
+        SelectionTarget {
+            amount: Amount::from_sat(amount),
+            fee_rate: FeeRate::ZERO,
+            long_term_fee_rate: FeeRate::ZERO,
+            cost_of_change: Amount::from_sat(100),
+        }
+    }
+
+    fn values(candidates: &[Candidate], mut selection: Vec<usize>) -> Vec<u64> {
+        selection.sort_unstable();
+        selection
+            .into_iter()
+            .map(|i| candidates[i].txout.value.to_sat())
+            .collect()
+    }
+
+    #[test]
+    fn input_weights() {
+        let c = &candidates(&[(1000, 1)])[0];
+        assert_eq!(c.weight(), Weight::from_wu(272));
+        assert_eq!(
+            c.fee(FeeRate::from_sat_per_vb_unchecked(10)),
+            Amount::from_sat(680)
+        );
+        assert_eq!(
+            c.effective_value(FeeRate::from_sat_per_vb_unchecked(10)),
+            320
+        );
+    }
+
+    #[test]
+    fn branch_and_bound_finds_changeless_match() {
+        let coins = candidates(&[(5000, 1), (3000, 2), (2500, 3), (1000, 4)]);
+        let selection = BranchAndBound::default()
+            .select(&coins, &target(6000))
+            .unwrap();
+        assert_eq!(values(&coins, selection), [5000, 1000]);
+
+        assert!(BranchAndBound::default()
+            .select(&coins, &target(11_501))
+            .is_none());
+        // No subset lands within the cost of change.
+        let coins = candidates(&[(5000, 1), (3000, 2)]);
+        assert!(BranchAndBound::default()
+            .select(&coins, &target(4000))
+            .is_none());
+    }
+
+    #[test]
+    fn knapsack_is_deterministic() {
+        let coins = candidates(&[(4000, 1), (3000, 2), (2500, 3), (1500, 4), (900, 5)]);
+        let knapsack = Knapsack {
+            min_change: Amount::from_sat(500),
+            ..Default::default()
+        };
+        let first = knapsack.select(&coins, &target(5000)).unwrap();
+        assert_eq!(first, knapsack.select(&coins, &target(5000)).unwrap());
+        let total: u64 = values(&coins, first).iter().sum();
+        assert!(total >= 5500);
+    }
+
+    #[test]
+    fn largest_first() {
+        let coins = candidates(&[(1000, 1), (5000, 2), (3000, 3)]);
+        let selection = LargestFirst.select(&coins, &target(6000)).unwrap();
+        assert_eq!(values(&coins, selection), [5000, 3000]);
+        assert!(LargestFirst.select(&coins, &target(9001)).is_none());
+    }
+
+    #[test]
+    fn privacy_spends_whole_addresses() {
+        let coins = candidates(&[(1000, 1), (5000, 2), (3000, 1), (8000, 3)]);
+        // Address 1 holds 4000 and covers the target on its own.
+        let selection = Privacy.select(&coins, &target(3500)).unwrap();
+        assert_eq!(values(&coins, selection), [1000, 3000]);
+
+        let selection = Privacy.select(&coins, &target(12_000)).unwrap();
+        assert_eq!(values(&coins, selection), [5000, 8000]);
+    }
+}
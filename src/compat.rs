@@ -0,0 +1,327 @@
+//! Adapters smoothing over the differences between Bitcoin Core versions.
+//!
+//! [Client](crate::client::Client) detects the server version on the first
+//! call to an adapted method and caches it. The adapters registered for that
+//! method and version then rewrite the request arguments before the call and
+//! the raw JSON response before it is deserialized, so the result types can
+//! assume the layout of recent versions.
+//!
+//! Methods removed from a version on are listed in the same table. Calling
+//! them on such a server fails with
+//! [Error::Unsupported](crate::error::Error::Unsupported), unless the
+//! [Client](crate::client::Client) wrapper translates the call into the
+//! methods replacing them.
+
+use std::{
+    collections::HashMap,
+    ops::{Bound, RangeBounds},
+};
+
+use bitcoincore_rpc::Error::UnexpectedStructure as err;
+use bitcoincore_rpc_json as json;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::client::Result;
+
+type RequestFn = Box<dyn Fn(&mut Vec<Value>) -> Result<()> + Send + Sync>;
+type ResponseFn = Box<dyn Fn(Value) -> Result<Value> + Send + Sync>;
+
+/// Rewrites the requests to, or responses of, a method for a range of server
+/// versions.
+///
+/// Versions are numbered like the "version" field of "getnetworkinfo", e.g.
+/// 190000 for 0.19.0 and 280000 for 28.0.
+pub struct Adapter {
+    method: String,
+    versions: (Bound<usize>, Bound<usize>),
+    request: Option<RequestFn>,
+    response: Option<ResponseFn>,
+}
+
+impl Adapter {
+    /// Creates an adapter of `method` for the server `versions`, which
+    /// changes nothing until [Self::request] or [Self::response] is set.
+    pub fn new(method: &str, versions: impl RangeBounds<usize>) -> Self {
+        Self {
+            method: method.to_owned(),
+            versions: (
+                versions.start_bound().cloned(),
+                versions.end_bound().cloned(),
+            ),
+            request: None,
+            response: None,
+        }
+    }
+
+    /// Rewrites the arguments before they are sent.
+    pub fn request(
+        mut self,
+        f: impl Fn(&mut Vec<Value>) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.request = Some(Box::new(f));
+        self
+    }
+
+    /// Rewrites the response before it is deserialized.
+    pub fn response(mut self, f: impl Fn(Value) -> Result<Value> + Send + Sync + 'static) -> Self {
+        self.response = Some(Box::new(f));
+        self
+    }
+
+    /// Returns true if the adapter applies to `method` on a server of
+    /// `version`.
+    pub fn applies(&self, method: &str, version: usize) -> bool {
+        self.method == method && self.versions.contains(&version)
+    }
+}
+
+/// The adapters a [Client](crate::client::Client) applies, in registration
+/// order.
+///
+/// The default table covers the known layout changes between 0.18 and 28:
+/// - "getblockchaininfo": the 0.18 "softforks" array and "bip9_softforks"
+///   map are merged into the "softforks" map of 0.19.
+/// - "getpeerinfo": "connection_type" is derived before 0.21, "addnode"
+///   after its removal in 22, and "startingheight" is -1 once removed in 28.
+///
+/// It also lists the removed methods:
+/// - "signrawtransaction" in 0.18, translated to "signrawtransactionwithkey"
+///   or "signrawtransactionwithwallet".
+/// - "generate" in 0.19, translated to "getnewaddress" and
+///   "generatetoaddress".
+/// - The legacy wallet methods, such as "importmulti" and "dumpprivkey", in
+///   30.
+pub struct Compat {
+    adapters: Vec<Adapter>,
+    removed: HashMap<String, usize>,
+}
+
+impl Compat {
+    /// Creates a table without any adapter or removed method.
+    pub fn empty() -> Self {
+        Self {
+            adapters: Vec::new(),
+            removed: HashMap::new(),
+        }
+    }
+
+    /// Records that servers from `version` on no longer have `method`.
+    pub fn remove(&mut self, method: &str, version: usize) {
+        self.removed.insert(method.to_owned(), version);
+    }
+
+    /// Returns true if a server of `version` no longer has `method`.
+    pub fn is_removed(&self, method: &str, version: usize) -> bool {
+        self.removed
+            .get(method)
+            .is_some_and(|&removed_in| version >= removed_in)
+    }
+
+    /// Adds `adapter`, applied after those already registered.
+    pub fn register(&mut self, adapter: Adapter) {
+        self.adapters.push(adapter);
+    }
+
+    /// Returns true if some adapter is registered for `method`, or it is
+    /// removed, whatever the version.
+    pub fn has(&self, method: &str) -> bool {
+        self.removed.contains_key(method) || self.adapters.iter().any(|a| a.method == method)
+    }
+
+    /// Rewrites the arguments of a call to `method` on a server of `version`.
+    pub fn adapt_request(&self, method: &str, version: usize, args: &mut Vec<Value>) -> Result<()> {
+        for adapter in self.adapters.iter().filter(|a| a.applies(method, version)) {
+            if let Some(f) = &adapter.request {
+                f(args)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rewrites the response of a call to `method` on a server of `version`.
+    pub fn adapt_response(&self, method: &str, version: usize, mut res: Value) -> Result<Value> {
+        for adapter in self.adapters.iter().filter(|a| a.applies(method, version)) {
+            if let Some(f) = &adapter.response {
+                res = f(res)?;
+            }
+        }
+        Ok(res)
+    }
+}
+
+impl Default for Compat {
+    fn default() -> Self {
+        let mut compat = Self::empty();
+        compat.register(Adapter::new("getblockchaininfo", ..190000).response(merge_softforks));
+        compat.register(Adapter::new("getpeerinfo", ..).response(|mut res| {
+            for peer in res.as_array_mut().ok_or(err)? {
+                peer_fields(peer.as_object_mut().ok_or(err)?);
+            }
+            Ok(res)
+        }));
+
+        compat.remove("signrawtransaction", 180000);
+        compat.remove("generate", 190000);
+        for method in [
+            "dumpprivkey",
+            "dumpwallet",
+            "importaddress",
+            "importmulti",
+            "importprivkey",
+            "importpubkey",
+            "importwallet",
+            "sethdseed",
+        ] {
+            compat.remove(method, 300000);
+        }
+        compat
+    }
+}
+
+/// Converts the 0.18 "softforks" array and "bip9_softforks" map into the
+/// "softforks" map of 0.19.
+fn merge_softforks(mut res: Value) -> Result<Value> {
+    let map = res.as_object_mut().ok_or(err)?;
+    let Some(bip9_softforks) = map.remove("bip9_softforks") else {
+        return Ok(res);
+    };
+    let old_softforks = map.remove("softforks").ok_or(err)?;
+
+    let mut softforks = Map::new();
+    for sf in old_softforks.as_array().ok_or(err)?.iter() {
+        let json = sf.as_object().ok_or(err)?;
+        let id = json.get("id").ok_or(err)?.as_str().ok_or(err)?;
+        let reject = json.get("reject").ok_or(err)?.as_object().ok_or(err)?;
+        let active = reject.get("status").ok_or(err)?.as_bool().ok_or(err)?;
+        let softfork = json::Softfork {
+            type_: json::SoftforkType::Buried,
+            bip9: None,
+            height: None,
+            active,
+        };
+        softforks.insert(id.into(), serde_json::to_value(softfork)?);
+    }
+    for (id, sf) in bip9_softforks.as_object().ok_or(err)?.iter() {
+        #[derive(Deserialize)]
+        struct OldBip9SoftFork {
+            pub status: json::Bip9SoftforkStatus,
+            pub bit: Option<u8>,
+            #[serde(rename = "startTime")]
+            pub start_time: i64,
+            pub timeout: u64,
+            pub since: u32,
+            pub statistics: Option<json::Bip9SoftforkStatistics>,
+        }
+        let sf: OldBip9SoftFork = serde_json::from_value(sf.clone())?;
+        let softfork = json::Softfork {
+            type_: json::SoftforkType::Bip9,
+            bip9: Some(json::Bip9SoftforkInfo {
+                status: sf.status,
+                bit: sf.bit,
+                start_time: sf.start_time,
+                timeout: sf.timeout,
+                since: sf.since,
+                statistics: sf.statistics,
+            }),
+            height: None,
+            active: sf.status == json::Bip9SoftforkStatus::Active,
+        };
+        softforks.insert(id.clone(), serde_json::to_value(softfork)?);
+    }
+    map.insert("softforks".into(), softforks.into());
+    Ok(res)
+}
+
+/// Fills in the "getpeerinfo" fields added or removed over time.
+fn peer_fields(peer: &mut Map<String, Value>) {
+    let inbound = peer.get("inbound").and_then(Value::as_bool);
+    let addnode = peer.get("addnode").and_then(Value::as_bool);
+    if !peer.contains_key("connection_type") {
+        let connection_type = match (inbound, addnode) {
+            (Some(true), _) => Some("inbound"),
+            (_, Some(true)) => Some("manual"),
+            (Some(false), Some(false)) => Some("outbound-full-relay"),
+            _ => None,
+        };
+        if let Some(connection_type) = connection_type {
+            peer.insert("connection_type".into(), connection_type.into());
+        }
+    }
+    if addnode.is_none() {
+        if let Some(connection_type) = peer.get("connection_type").and_then(Value::as_str) {
+            let manual = connection_type == "manual";
+            peer.insert("addnode".into(), manual.into());
+        }
+    }
+    peer.entry("startingheight").or_insert((-1).into());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn adapters_are_keyed_by_version() {
+        let compat = Compat::default();
+        let res = json!({ "softforks": [], "bip9_softforks": {} });
+        let old = compat
+            .adapt_response("getblockchaininfo", 180100, res.clone())
+            .unwrap();
+        assert_eq!(old, json!({ "softforks": {} }));
+        let new = compat
+            .adapt_response("getblockchaininfo", 190000, res)
+            .unwrap();
+        assert_eq!(new, json!({ "softforks": [], "bip9_softforks": {} }));
+        assert!(!compat.has("getblockcount"));
+        assert!(!compat.has("getnetworkinfo"));
+        assert!(compat.has("generate"));
+        assert!(!compat.is_removed("generate", 180100));
+        assert!(compat.is_removed("generate", 190000));
+        assert!(compat.is_removed("importmulti", 300000));
+        assert!(!compat.is_removed("importdescriptors", 300000));
+
+        let mut compat = Compat::empty();
+        compat.register(Adapter::new("getblock", 230000..).request(|args| {
+            args.push(3.into());
+            Ok(())
+        }));
+        let mut args = vec![];
+        compat.adapt_request("getblock", 220000, &mut args).unwrap();
+        assert!(args.is_empty());
+        compat.adapt_request("getblock", 230000, &mut args).unwrap();
+        assert_eq!(args, vec![json!(3)]);
+    }
+
+    #[test]
+    fn converts_old_layouts() {
+        let res = json!({
+            "softforks": [{ "id": "bip66", "version": 3, "reject": { "status": true } }],
+            "bip9_softforks": {
+                "segwit": { "status": "active", "startTime": 1479168000, "timeout": 1510704000, "since": 481824 }
+            },
+            "warnings": "",
+        });
+        #[derive(Deserialize)]
+        struct Info {
+            softforks: std::collections::HashMap<String, json::Softfork>,
+        }
+        let info: Info = serde_json::from_value(
+            Compat::default()
+                .adapt_response("getblockchaininfo", 180000, res)
+                .unwrap(),
+        )
+        .unwrap();
+        assert!(info.softforks["bip66"].active);
+        assert_eq!(info.softforks["segwit"].type_, json::SoftforkType::Bip9);
+
+        let mut peer = json!({ "inbound": false, "addnode": true });
+        peer_fields(peer.as_object_mut().unwrap());
+        assert_eq!(peer["connection_type"], "manual");
+        let mut peer = json!({ "inbound": true, "connection_type": "inbound" });
+        peer_fields(peer.as_object_mut().unwrap());
+        assert_eq!(peer["addnode"], false);
+        assert_eq!(peer["startingheight"], -1);
+    }
+}
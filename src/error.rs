@@ -1,9 +1,17 @@
 use bitcoincore_rpc::Error as BitcoinCoreRpcError;
+use bitcoincore_rpc_json::bitcoin::{
+    bip158::{self, FilterHeader},
+    consensus::encode,
+    merkle_tree::MerkleBlockError,
+    sign_message::MessageSignatureError,
+    Amount, BlockHash, Txid,
+};
 use reqwest::Error as ReqwestError;
 use thiserror::Error;
+use tokio::task::JoinError;
 use url::ParseError;
 
-use crate::jsonrpc::JsonRpcError;
+use crate::{broadcast::RejectReason, headers::ValidationError, jsonrpc::JsonRpcError};
 
 /// Errors for relay requests.
 #[derive(Error, Debug)]
@@ -35,4 +43,88 @@ pub enum Error {
     /// The url failed.
     #[error(transparent)]
     UrlParseError(#[from] ParseError),
+    /// The consensus data could not be decoded.
+    #[error(transparent)]
+    ConsensusEncode(#[from] encode::Error),
+    /// The merkle proof is malformed or does not hash to the header's root.
+    #[error(transparent)]
+    MerkleBlock(#[from] MerkleBlockError),
+    /// The data commits to a different block than the one it was checked against.
+    #[error("Header mismatch: expected block {expected}, found {found}")]
+    HeaderMismatch {
+        expected: BlockHash,
+        found: BlockHash,
+    },
+    /// The block filter could not be decoded.
+    #[error(transparent)]
+    Bip158(#[from] bip158::Error),
+    /// The block filter does not chain to the expected filter header.
+    #[error("Filter header mismatch for block {block_hash}: expected {expected}, found {found}")]
+    FilterHeaderMismatch {
+        block_hash: BlockHash,
+        expected: FilterHeader,
+        found: FilterHeader,
+    },
+    /// Neither replacing the transaction nor spending one of its outputs
+    /// can raise its fee.
+    #[error("Cannot bump fee of {txid}: {reason}")]
+    CannotBump { txid: Txid, reason: String },
+    /// Both the replacement and the child paying for a stuck transaction
+    /// failed.
+    #[error("Cannot bump fee of {txid}: bumpfee failed ({rbf}), then CPFP failed ({cpfp})")]
+    BumpFailed {
+        txid: Txid,
+        rbf: Box<Error>,
+        cpfp: Box<Error>,
+    },
+    /// The server version no longer has the method, and the call could not
+    /// be translated into the methods replacing it.
+    #[error("{method} is not supported by server version {server_version}")]
+    Unsupported {
+        method: String,
+        server_version: usize,
+    },
+    /// The block store failed.
+    #[cfg(feature = "store")]
+    #[error(transparent)]
+    Store(Box<redb::Error>),
+    /// The loaded UTXO snapshot is neither validated nor being validated.
+    #[error("Snapshot based on block {base_hash} is not being validated")]
+    SnapshotNotValidating { base_hash: BlockHash },
+    /// A background task panicked or was cancelled.
+    #[error(transparent)]
+    TaskJoin(#[from] JoinError),
+    /// A header or block served by the node failed validation.
+    #[error("Block {hash} failed validation: {err}")]
+    Validation {
+        hash: BlockHash,
+        #[source]
+        err: ValidationError,
+    },
+    /// An index cannot apply or undo the block, e.g. after a reorg deeper
+    /// than it remembers, and must be rebuilt.
+    #[error("Index out of sync with the chain at block {hash}")]
+    IndexOutOfSync { hash: BlockHash },
+    /// The best chain forked below the blocks remembered to detect reorgs,
+    /// disconnecting at least `depth` of the blocks processed.
+    #[error("Reorg of at least {depth} blocks, deeper than the blocks remembered")]
+    ReorgTooDeep { depth: u64 },
+    /// The node refused to accept the transaction.
+    #[error("Transaction {txid} rejected: {reason}")]
+    Rejected { txid: Txid, reason: RejectReason },
+    /// The extranonce space to reserve in the coinbase is out of range.
+    #[error("Invalid extranonce size of {size} bytes, expected {min} to {max}")]
+    InvalidExtranonceSize { size: usize, min: usize, max: usize },
+    /// The extranonce does not fill the space reserved in the coinbase.
+    #[error("Extranonce mismatch: expected {expected} bytes, found {found}")]
+    ExtranonceMismatch { expected: usize, found: usize },
+    /// The node would not accept the block.
+    #[error("Block {hash} rejected: {reason}")]
+    BlockRejected { hash: BlockHash, reason: String },
+    /// The candidates cannot pay for the outputs and the fee.
+    #[error("Insufficient funds: needed {needed}, available {available}")]
+    InsufficientFunds { needed: Amount, available: Amount },
+    /// A signed message could not be decoded or verified.
+    #[error(transparent)]
+    MessageSignature(#[from] MessageSignatureError),
 }
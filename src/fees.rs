@@ -0,0 +1,377 @@
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bitcoincore_rpc_json::{
+    bitcoin::{Amount, FeeRate},
+    EstimateMode, EstimateSmartFeeResult, GetMempoolInfoResult,
+};
+use serde_json::Value;
+use tokio::sync::Mutex;
+
+use crate::{
+    client::{Client, Result},
+    types::EstimateRawFeeResult,
+};
+
+/// Confirmation targets queried by default, in blocks.
+pub const DEFAULT_TARGETS: &[u16] = &[1, 2, 3, 6, 12, 24, 48, 144, 504, 1008];
+
+/// How long estimates are served from the cache by default.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60);
+
+/// Virtual size of the transactions fitting in one block.
+const BLOCK_VSIZE: u64 = 1_000_000;
+
+/// Where a fee estimate came from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FeeSource {
+    /// `estimatesmartfee`.
+    SmartFee,
+    /// `estimaterawfee`, used when `estimatesmartfee` lacked data.
+    RawFee,
+    /// The current mempool, as seen by `getrawmempool`.
+    Mempool,
+    /// Borrowed from the given shorter target, which pays at least as much.
+    ShorterTarget(u16),
+    /// The configured fallback rate.
+    Fallback,
+    /// The node's minimum mempool fee rate.
+    MempoolMinFee,
+}
+
+/// A fee rate for a confirmation target.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FeeEstimate {
+    pub target: u16,
+    pub fee_rate: FeeRate,
+    pub source: FeeSource,
+}
+
+/// A snapshot of fee estimates for a ladder of confirmation targets.
+#[derive(Clone, Debug)]
+pub struct FeeEstimates {
+    estimates: BTreeMap<u16, FeeEstimate>,
+    fetched_at: Instant,
+}
+
+impl FeeEstimates {
+    /// Returns the estimate for the smallest ladder target that is at least
+    /// `target`, or for the largest one if `target` is beyond the ladder.
+    pub fn get(&self, target: u16) -> Option<&FeeEstimate> {
+        self.estimates
+            .range(target..)
+            .next()
+            .or_else(|| self.estimates.iter().next_back())
+            .map(|(_, e)| e)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &FeeEstimate> {
+        self.estimates.values()
+    }
+
+    pub fn fetched_at(&self) -> Instant {
+        self.fetched_at
+    }
+}
+
+/// Estimates fee rates for a ladder of confirmation targets.
+///
+/// All targets are queried with `estimatesmartfee` in a single batch.
+/// Targets the node has insufficient data for fall back to `estimaterawfee`,
+/// then to the estimate of a shorter target, then to the configured fallback
+/// and finally to the node's minimum mempool fee. When enabled, the current
+/// mempool is blended in so estimates react to congestion the node's
+/// historical data has not caught up with yet.
+///
+/// Estimates are cached for a configurable time.
+pub struct FeeEstimator {
+    client: Arc<Client>,
+    targets: Vec<u16>,
+    mode: Option<EstimateMode>,
+    ttl: Duration,
+    use_mempool: bool,
+    fallback: Option<FeeRate>,
+    cache: Mutex<Option<Arc<FeeEstimates>>>,
+}
+
+impl FeeEstimator {
+    pub fn new(client: Arc<Client>) -> Self {
+        Self {
+            client,
+            targets: DEFAULT_TARGETS.to_vec(),
+            mode: None,
+            ttl: DEFAULT_TTL,
+            use_mempool: false,
+            fallback: None,
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// Sets the confirmation targets to estimate, in blocks.
+    pub fn targets(mut self, targets: &[u16]) -> Self {
+        self.targets = targets.to_vec();
+        self.targets.sort_unstable();
+        self.targets.dedup();
+        self
+    }
+
+    /// Sets the `estimatesmartfee` estimate mode.
+    pub fn mode(mut self, mode: EstimateMode) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Sets how long estimates are served from the cache.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Blends in a fee rate histogram built from `getrawmempool`.
+    pub fn use_mempool(mut self, use_mempool: bool) -> Self {
+        self.use_mempool = use_mempool;
+        self
+    }
+
+    /// Sets the fee rate used when no estimate is available for a target.
+    pub fn fallback(mut self, fee_rate: FeeRate) -> Self {
+        self.fallback = Some(fee_rate);
+        self
+    }
+
+    /// Returns the fee rate for `target`, see [FeeEstimates::get].
+    pub async fn fee_rate(&self, target: u16) -> Result<FeeRate> {
+        let estimates = self.estimates().await?;
+        Ok(estimates
+            .get(target)
+            .map_or(FeeRate::BROADCAST_MIN, |e| e.fee_rate))
+    }
+
+    /// Returns the cached estimates, refreshing them once they expire.
+    pub async fn estimates(&self) -> Result<Arc<FeeEstimates>> {
+        let mut cache = self.cache.lock().await;
+        if let Some(estimates) = cache.as_ref() {
+            if estimates.fetched_at.elapsed() < self.ttl {
+                return Ok(estimates.clone());
+            }
+        }
+        let estimates = Arc::new(self.fetch().await?);
+        *cache = Some(estimates.clone());
+        Ok(estimates)
+    }
+
+    /// Drops the cached estimates.
+    pub async fn invalidate(&self) {
+        *self.cache.lock().await = None;
+    }
+
+    async fn fetch(&self) -> Result<FeeEstimates> {
+        let mode = self.mode.map(serde_json::to_value).transpose()?;
+        let smart_args: Vec<Vec<Value>> = self
+            .targets
+            .iter()
+            .map(|t| {
+                std::iter::once(Value::from(*t))
+                    .chain(mode.clone())
+                    .collect()
+            })
+            .collect();
+        let mut calls: Vec<(&str, &[Value])> = vec![("getmempoolinfo", &[])];
+        calls.extend(
+            smart_args
+                .iter()
+                .map(|a| ("estimatesmartfee", a.as_slice())),
+        );
+
+        let mut results = self.client.call_batch::<Value>(&calls).await?.into_iter();
+        let mempool_info: GetMempoolInfoResult =
+            serde_json::from_value(results.next().expect("one result per call")?)?;
+
+        let mut rates: BTreeMap<u16, (FeeRate, FeeSource)> = BTreeMap::new();
+        for (target, result) in self.targets.iter().zip(results) {
+            // RPC errors, e.g. for targets the node does not track, and
+            // "Insufficient data" answers both leave the target to a fallback.
+            let rate = result
+                .ok()
+                .and_then(|v| serde_json::from_value::<EstimateSmartFeeResult>(v).ok())
+                .and_then(|r| r.fee_rate);
+            if let Some(rate) = rate {
+                rates.insert(*target, (btc_per_kvb(rate), FeeSource::SmartFee));
+            }
+        }
+
+        let missing: Vec<u16> = self
+            .targets
+            .iter()
+            .copied()
+            .filter(|t| !rates.contains_key(t))
+            .collect();
+        if !missing.is_empty() {
+            let raw_args: Vec<[Value; 1]> = missing.iter().map(|t| [Value::from(*t)]).collect();
+            let calls: Vec<(&str, &[Value])> = raw_args
+                .iter()
+                .map(|a| ("estimaterawfee", a.as_slice()))
+                .collect();
+            let results = self.client.call_batch::<EstimateRawFeeResult>(&calls);
+            for (target, result) in missing.iter().zip(results.await?) {
+                if let Some(rate) = result.ok().and_then(|r| r.fee_rate()) {
+                    rates.insert(*target, (btc_per_kvb(rate), FeeSource::RawFee));
+                }
+            }
+        }
+
+        if self.use_mempool {
+            let mempool = self.client.get_raw_mempool_verbose().await?;
+            let entries: Vec<(Amount, u64)> = mempool
+                .values()
+                .map(|e| {
+                    // Rank by the lower of the own and ancestor package rates
+                    // to approximate mining order.
+                    let own = u128::from(e.fees.modified.to_sat()) * u128::from(e.ancestor_size);
+                    let package = u128::from(e.fees.ancestor.to_sat()) * u128::from(e.vsize);
+                    if own <= package {
+                        (e.fees.modified, e.vsize)
+                    } else {
+                        (e.fees.ancestor, e.ancestor_size)
+                    }
+                })
+                .collect();
+            for target in &self.targets {
+                let Some(mempool_rate) =
+                    histogram_fee_rate(entries.iter().copied(), u64::from(*target) * BLOCK_VSIZE)
+                else {
+                    continue;
+                };
+                match rates.get(target) {
+                    Some((rate, _)) if *rate >= mempool_rate => {}
+                    _ => {
+                        rates.insert(*target, (mempool_rate, FeeSource::Mempool));
+                    }
+                }
+            }
+        }
+
+        let min_fee = btc_per_kvb(mempool_info.mempool_min_fee);
+        let mut estimates = BTreeMap::new();
+        let mut shorter: Option<(u16, FeeRate)> = None;
+        for target in &self.targets {
+            let (fee_rate, source) = match rates.get(target) {
+                Some(found) => *found,
+                None => match (shorter, self.fallback) {
+                    (Some((t, rate)), _) => (rate, FeeSource::ShorterTarget(t)),
+                    (None, Some(rate)) => (rate, FeeSource::Fallback),
+                    (None, None) => (min_fee, FeeSource::MempoolMinFee),
+                },
+            };
+            let fee_rate = fee_rate.max(min_fee);
+            if rates.contains_key(target) {
+                shorter = Some((*target, fee_rate));
+            }
+            estimates.insert(
+                *target,
+                FeeEstimate {
+                    target: *target,
+                    fee_rate,
+                    source,
+                },
+            );
+        }
+
+        // A longer target never needs to pay more than a shorter one.
+        let mut cap = FeeRate::MAX;
+        for estimate in estimates.values_mut() {
+            estimate.fee_rate = estimate.fee_rate.min(cap);
+            cap = estimate.fee_rate;
+        }
+
+        Ok(FeeEstimates {
+            estimates,
+            fetched_at: Instant::now(),
+        })
+    }
+}
+
+/// Converts a BTC/kvB rate as reported by the node, rounding up.
+fn btc_per_kvb(rate: Amount) -> FeeRate {
+    FeeRate::from_sat_per_kwu(rate.to_sat().div_ceil(4))
+}
+
+/// Returns the fee rate needed to be included in the first `vsize` virtual
+/// bytes of the mempool, or `None` if the whole mempool fits.
+///
+/// `entries` are `(fee, vsize)` pairs.
+fn histogram_fee_rate(
+    entries: impl IntoIterator<Item = (Amount, u64)>,
+    vsize: u64,
+) -> Option<FeeRate> {
+    let mut rates: Vec<(FeeRate, u64)> = entries
+        .into_iter()
+        .filter(|(_, size)| *size > 0)
+        .map(|(fee, size)| (FeeRate::from_sat_per_kwu(fee.to_sat() * 250 / size), size))
+        .collect();
+    rates.sort_unstable_by_key(|(rate, _)| Reverse(*rate));
+
+    let mut total = 0;
+    for (rate, size) in rates {
+        total += size;
+        if total >= vsize {
+            return Some(rate);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_node_fee_rates() {
+        // 0.00001 BTC/kvB is 1 sat/vB.
+        let rate = btc_per_kvb(Amount::from_sat(1_000));
+        assert_eq!(rate.to_sat_per_vb_floor(), 1);
+        assert_eq!(btc_per_kvb(Amount::from_sat(1_001)).to_sat_per_kwu(), 251);
+    }
+
+    #[test]
+    fn histogram_picks_rate_at_depth() {
+        let entries = [
+            (Amount::from_sat(10_000), 500_000),     // 0.02 sat/vB
+            (Amount::from_sat(50_000_000), 500_000), // 100 sat/vB
+            (Amount::from_sat(5_000_000), 500_000),  // 10 sat/vB
+        ];
+        let rate = histogram_fee_rate(entries, BLOCK_VSIZE).unwrap();
+        assert_eq!(rate.to_sat_per_vb_floor(), 10);
+
+        let rate = histogram_fee_rate(entries, 400_000).unwrap();
+        assert_eq!(rate.to_sat_per_vb_floor(), 100);
+
+        assert_eq!(histogram_fee_rate(entries, 2 * BLOCK_VSIZE), None);
+    }
+
+    #[test]
+    fn estimates_round_up_to_ladder() {
+        let estimates = FeeEstimates {
+            estimates: [2, 6, 144]
+                .into_iter()
+                .map(|target| {
+                    let estimate = FeeEstimate {
+                        target,
+                        fee_rate: FeeRate::from_sat_per_vb_unchecked(u64::from(200 / target)),
+                        source: FeeSource::SmartFee,
+                    };
+                    (target, estimate)
+                })
+                .collect(),
+            fetched_at: Instant::now(),
+        };
+        assert_eq!(estimates.get(1).unwrap().target, 2);
+        assert_eq!(estimates.get(3).unwrap().target, 6);
+        assert_eq!(estimates.get(6).unwrap().target, 6);
+        assert_eq!(estimates.get(1000).unwrap().target, 144);
+    }
+}
@@ -0,0 +1,266 @@
+use bitcoincore_rpc_json::{
+    bitcoin::{
+        bip158::{BlockFilter, FilterHeader},
+        hashes::Hash,
+        Block, BlockHash, ScriptBuf,
+    },
+    GetBlockFilterResult,
+};
+
+use crate::{
+    client::{Client, Result},
+    error::Error,
+};
+
+/// A block whose basic filter matched one of the watched scripts.
+#[derive(Debug, Clone)]
+pub struct FilterMatch {
+    pub height: u64,
+    pub block: Block,
+}
+
+/// Scans the chain for watched scripts using BIP158 basic block filters.
+///
+/// Filters are fetched with `getblockfilter`, which requires the node to run
+/// with `-blockfilterindex`. Only blocks whose filter matches are downloaded
+/// with `getblock`.
+///
+/// The filters and their headers all come from the node, so they can only be
+/// trusted as much as the node unless the scan is checked against a filter
+/// header from another source, see [Self::scan_checked].
+pub struct FilterScanner<'a> {
+    client: &'a Client,
+    scripts: Vec<ScriptBuf>,
+}
+
+impl<'a> FilterScanner<'a> {
+    pub fn new(client: &'a Client) -> Self {
+        Self {
+            client,
+            scripts: Vec::new(),
+        }
+    }
+
+    /// Watches a scriptPubKey.
+    pub fn add_script(&mut self, script: ScriptBuf) {
+        self.scripts.push(script);
+    }
+
+    /// Watches every address derived from `descriptor`, using the node's
+    /// `deriveaddresses`. Ranged descriptors need a `range`.
+    pub async fn add_descriptor(
+        &mut self,
+        descriptor: &str,
+        range: Option<[u32; 2]>,
+    ) -> Result<()> {
+        let addresses = self.client.derive_addresses(descriptor, range).await?;
+        self.scripts.extend(
+            addresses
+                .into_iter()
+                .map(|a| a.assume_checked().script_pubkey()),
+        );
+        Ok(())
+    }
+
+    pub fn scripts(&self) -> &[ScriptBuf] {
+        &self.scripts
+    }
+
+    /// Scans blocks `start..=end` and returns the blocks whose filter matches
+    /// any watched script.
+    ///
+    /// Each filter is only checked against the filter header the node
+    /// reports with it, which does not detect a node serving forged filters.
+    pub async fn scan(&self, start: u64, end: u64) -> Result<Vec<FilterMatch>> {
+        self.scan_inner(start, end, None).await
+    }
+
+    /// Like [Self::scan], but also checks that the filters chain to
+    /// `checkpoint`, the filter header of block `end` obtained from a trusted
+    /// source, before any block is downloaded.
+    ///
+    /// A filter omitting a watched script then fails the scan with
+    /// [Error::FilterHeaderMismatch], whatever headers the node reports.
+    pub async fn scan_checked(
+        &self,
+        start: u64,
+        end: u64,
+        checkpoint: FilterHeader,
+    ) -> Result<Vec<FilterMatch>> {
+        self.scan_inner(start, end, Some(checkpoint)).await
+    }
+
+    async fn scan_inner(
+        &self,
+        start: u64,
+        end: u64,
+        checkpoint: Option<FilterHeader>,
+    ) -> Result<Vec<FilterMatch>> {
+        let mut prev_header = match start.checked_sub(1) {
+            None => FilterHeader::all_zeros(),
+            Some(height) => {
+                let hash = self.client.get_block_hash(height).await?;
+                filter_header(&self.client.get_block_filter(&hash).await?)
+            }
+        };
+        let mut end_hash = None;
+        let mut matched = Vec::new();
+        for height in start..=end {
+            let hash = self.client.get_block_hash(height).await?;
+            let result = self.client.get_block_filter(&hash).await?;
+            prev_header = verify_filter_header(&hash, &prev_header, &result)?;
+            if self.matches(&hash, &result.into_filter())? {
+                matched.push((height, hash));
+            }
+            end_hash = Some(hash);
+        }
+        if let (Some(expected), Some(block_hash)) = (checkpoint, end_hash) {
+            if prev_header != expected {
+                return Err(Error::FilterHeaderMismatch {
+                    block_hash,
+                    expected,
+                    found: prev_header,
+                });
+            }
+        }
+
+        let mut matches = Vec::with_capacity(matched.len());
+        for (height, hash) in matched {
+            let block = self.client.get_block(&hash).await?;
+            if block.block_hash() != hash {
+                return Err(Error::HeaderMismatch {
+                    expected: hash,
+                    found: block.block_hash(),
+                });
+            }
+            matches.push(FilterMatch { height, block });
+        }
+        Ok(matches)
+    }
+
+    /// Returns true if `filter` matches any watched script.
+    pub fn matches(&self, block_hash: &BlockHash, filter: &BlockFilter) -> Result<bool> {
+        if self.scripts.is_empty() {
+            return Ok(false);
+        }
+        Ok(filter.match_any(block_hash, self.scripts.iter().map(|s| s.as_bytes()))?)
+    }
+}
+
+/// Checks that the filter in `result` chains from `prev_header` to the filter
+/// header reported alongside it, and returns that header.
+///
+/// This only proves the filter consistent with the reported header: the
+/// chain must still end at a trusted filter header to detect a forged one.
+pub fn verify_filter_header(
+    block_hash: &BlockHash,
+    prev_header: &FilterHeader,
+    result: &GetBlockFilterResult,
+) -> Result<FilterHeader> {
+    let expected = result.to_filter().filter_header(prev_header);
+    let found = filter_header(result);
+    if expected != found {
+        return Err(Error::FilterHeaderMismatch {
+            block_hash: *block_hash,
+            expected,
+            found,
+        });
+    }
+    Ok(found)
+}
+
+/// The filter header reported by `getblockfilter`.
+fn filter_header(result: &GetBlockFilterResult) -> FilterHeader {
+    // `getblockfilter` returns the filter header, which bitcoincore-rpc-json
+    // types as a filter hash.
+    FilterHeader::from_byte_array(result.header.to_byte_array())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc_json::bitcoin::{
+        absolute::LockTime, bip158::FilterHash, block, transaction, Amount, CompactTarget,
+        OutPoint, Sequence, Transaction, TxIn, TxMerkleNode, TxOut, Witness,
+    };
+
+    fn block_paying_to(script: ScriptBuf) -> Block {
+        let coinbase = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::from_bytes(vec![1, 1]),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(50),
+                script_pubkey: script,
+            }],
+        };
+        Block {
+            header: block::Header {
+                version: block::Version::TWO,
+                prev_blockhash: BlockHash::all_zeros(),
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: 0,
+                bits: CompactTarget::from_consensus(0x207fffff),
+                nonce: 0,
+            },
+            txdata: vec![coinbase],
+        }
+    }
+
+    fn result_for(filter: &BlockFilter, header: FilterHeader) -> GetBlockFilterResult {
+        GetBlockFilterResult {
+            header: FilterHash::from_byte_array(header.to_byte_array()),
+            filter: filter.content.clone(),
+        }
+    }
+
+    #[test]
+    fn filter_header_chain() {
+        let block = block_paying_to(ScriptBuf::from_bytes(vec![0x51]));
+        let filter =
+            BlockFilter::new_script_filter(&block, |_| -> std::result::Result<ScriptBuf, _> {
+                unreachable!()
+            })
+            .unwrap();
+        let prev = FilterHeader::all_zeros();
+        let header = filter.filter_header(&prev);
+
+        let result = result_for(&filter, header);
+        assert_eq!(
+            verify_filter_header(&block.block_hash(), &prev, &result).unwrap(),
+            header
+        );
+
+        let forged = result_for(&filter, FilterHeader::all_zeros());
+        assert!(matches!(
+            verify_filter_header(&block.block_hash(), &prev, &forged),
+            Err(Error::FilterHeaderMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn matches_watched_scripts() {
+        let watched = ScriptBuf::from_bytes(vec![0x00, 0x14, 0xaa]);
+        let block = block_paying_to(watched.clone());
+        let filter =
+            BlockFilter::new_script_filter(&block, |_| -> std::result::Result<ScriptBuf, _> {
+                unreachable!()
+            })
+            .unwrap();
+
+        let client = Client::new("http://127.0.0.1:8332", bitcoincore_rpc::Auth::None).unwrap();
+        let mut scanner = FilterScanner::new(&client);
+        assert!(!scanner.matches(&block.block_hash(), &filter).unwrap());
+
+        scanner.add_script(ScriptBuf::from_bytes(vec![0x51, 0x52]));
+        assert!(!scanner.matches(&block.block_hash(), &filter).unwrap());
+
+        scanner.add_script(watched);
+        assert!(scanner.matches(&block.block_hash(), &filter).unwrap());
+    }
+}
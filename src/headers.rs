@@ -0,0 +1,510 @@
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bitcoincore_rpc_json::bitcoin::{
+    block::Header, consensus::Params, constants::genesis_block, Block, BlockHash, Network, Target,
+    Work,
+};
+use thiserror::Error;
+
+use crate::{
+    client::{Client, Result},
+    error::Error,
+};
+
+/// How far ahead of the local clock a header timestamp may be, in seconds.
+pub const MAX_FUTURE_BLOCK_TIME: u32 = 2 * 60 * 60;
+
+/// Number of previous headers used to compute the median time past.
+const MEDIAN_TIME_SPAN: usize = 11;
+
+/// Reasons a header or block is rejected by [HeaderVerifier].
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The header does not build on the expected previous block.
+    #[error("does not connect to previous block {0}")]
+    Disconnected(BlockHash),
+    /// The header's `bits` differ from the difficulty-retarget rules.
+    #[error("unexpected difficulty bits {found:#010x}, expected {expected:#010x}")]
+    BadDifficultyBits { expected: u32, found: u32 },
+    /// The header hash does not meet its target.
+    #[error("insufficient proof of work")]
+    InsufficientWork,
+    /// The timestamp is not greater than the median of the previous blocks.
+    #[error("timestamp {time} is not after median time past {median}")]
+    TimeTooOld { time: u32, median: u32 },
+    /// The timestamp is too far ahead of the local clock.
+    #[error("timestamp {time} is too far in the future")]
+    TimeTooNew { time: u32 },
+    /// The block is not part of the verified chain.
+    #[error("block is not part of the verified chain")]
+    UnknownHeader,
+    /// The transactions do not hash to the header's merkle root.
+    #[error("merkle root mismatch")]
+    MerkleRootMismatch,
+    /// The coinbase witness commitment does not match the transactions.
+    #[error("witness commitment mismatch")]
+    WitnessCommitmentMismatch,
+    /// The node follows a chain that forks off before the trusted checkpoint.
+    #[error("chain forks below the trusted checkpoint")]
+    ForkBelowCheckpoint,
+    /// Checkpoints must sit on a difficulty adjustment boundary.
+    #[error("checkpoint height {0} is not a difficulty adjustment boundary")]
+    MisalignedCheckpoint(u64),
+}
+
+/// Validates block headers served by an untrusted node.
+///
+/// Starting from the genesis block or a trusted checkpoint, every header is
+/// checked for its link to the previous header, proof of work, the
+/// difficulty-retarget rules of the network and its timestamp. The verifier
+/// keeps the chain with the most work it has validated so far.
+#[derive(Debug, Clone)]
+pub struct HeaderVerifier {
+    params: Params,
+    base_height: u64,
+    headers: Vec<Header>,
+    index: HashMap<BlockHash, u64>,
+}
+
+impl HeaderVerifier {
+    /// Creates a verifier starting at the genesis block of `network`.
+    pub fn new(network: Network) -> Self {
+        let params = Params::new(network);
+        let genesis = genesis_block(&params).header;
+        Self {
+            params,
+            base_height: 0,
+            index: HashMap::from([(genesis.block_hash(), 0)]),
+            headers: vec![genesis],
+        }
+    }
+
+    /// Creates a verifier starting at a trusted checkpoint.
+    ///
+    /// On networks with difficulty retargeting, `height` must be a multiple of
+    /// the difficulty adjustment interval.
+    pub fn from_checkpoint(network: Network, height: u64, header: Header) -> Result<Self> {
+        let params = Params::new(network);
+        if !params.no_pow_retargeting && height % params.difficulty_adjustment_interval() != 0 {
+            return Err(Error::Validation {
+                hash: header.block_hash(),
+                err: ValidationError::MisalignedCheckpoint(height),
+            });
+        }
+        Ok(Self {
+            params,
+            base_height: height,
+            index: HashMap::from([(header.block_hash(), height)]),
+            headers: vec![header],
+        })
+    }
+
+    pub fn network(&self) -> Network {
+        self.params.network
+    }
+
+    /// Height of the best validated header.
+    pub fn tip_height(&self) -> u64 {
+        self.base_height + self.headers.len() as u64 - 1
+    }
+
+    /// The best validated header.
+    pub fn tip(&self) -> &Header {
+        self.headers.last().expect("chain is never empty")
+    }
+
+    /// Returns the validated header at `height`, if any.
+    pub fn header_at(&self, height: u64) -> Option<&Header> {
+        let idx = height.checked_sub(self.base_height)?;
+        self.headers.get(idx as usize)
+    }
+
+    /// Returns the height of a header on the validated chain.
+    pub fn height_of(&self, hash: &BlockHash) -> Option<u64> {
+        self.index.get(hash).copied()
+    }
+
+    /// Iterates over the validated chain, from the genesis or checkpoint up.
+    pub fn headers(&self) -> impl Iterator<Item = (u64, &Header)> {
+        (self.base_height..).zip(self.headers.iter())
+    }
+
+    /// Total work of the validated chain above the genesis or checkpoint.
+    pub fn chain_work(&self) -> Option<Work> {
+        sum_work(&self.headers[1..])
+    }
+
+    /// Validates `header` and appends it to the tip of the chain.
+    ///
+    /// Returns the height of the new tip.
+    pub fn connect(&mut self, header: Header) -> Result<u64> {
+        let branch = Branch {
+            params: &self.params,
+            base_height: self.base_height,
+            trunk: &self.headers,
+            extra: Vec::new(),
+        };
+        branch.check(&header)?;
+        self.push(header);
+        Ok(self.tip_height())
+    }
+
+    /// Downloads and validates headers from the node up to its tip.
+    ///
+    /// If the node reports a different chain than the one validated so far,
+    /// its branch is validated from the fork point and adopted only when it
+    /// carries more work. Returns the height of the validated tip.
+    pub async fn sync(&mut self, client: &Client) -> Result<u64> {
+        let node_height = client.get_block_count().await?;
+        if node_height < self.base_height {
+            // The node has not reached the checkpoint yet, nothing to learn.
+            return Ok(self.tip_height());
+        }
+
+        let mut fork = self.tip_height().min(node_height);
+        loop {
+            let hash = client.get_block_hash(fork).await?;
+            if self.header_at(fork).map(Header::block_hash) == Some(hash) {
+                break;
+            }
+            if fork == self.base_height {
+                return Err(Error::Validation {
+                    hash,
+                    err: ValidationError::ForkBelowCheckpoint,
+                });
+            }
+            fork -= 1;
+        }
+
+        let trunk_len = (fork - self.base_height + 1) as usize;
+        let mut branch = Branch {
+            params: &self.params,
+            base_height: self.base_height,
+            trunk: &self.headers[..trunk_len],
+            extra: Vec::new(),
+        };
+        for height in fork + 1..=node_height {
+            let hash = client.get_block_hash(height).await?;
+            let header = client.get_block_header(&hash).await?;
+            if header.block_hash() != hash {
+                return Err(Error::HeaderMismatch {
+                    expected: hash,
+                    found: header.block_hash(),
+                });
+            }
+            branch.check(&header)?;
+            branch.extra.push(header);
+        }
+
+        let extra = branch.extra;
+        if sum_work(&extra) > sum_work(&self.headers[trunk_len..]) {
+            for header in self.headers.drain(trunk_len..) {
+                self.index.remove(&header.block_hash());
+            }
+            for header in extra {
+                self.push(header);
+            }
+        }
+        Ok(self.tip_height())
+    }
+
+    /// Checks that `block` belongs to the validated chain and that its
+    /// transactions match the header's merkle root and witness commitment.
+    ///
+    /// Returns the height of the block.
+    pub fn verify_block(&self, block: &Block) -> Result<u64> {
+        let hash = block.block_hash();
+        let err = match self.height_of(&hash) {
+            None => ValidationError::UnknownHeader,
+            Some(_) if !block.check_merkle_root() => ValidationError::MerkleRootMismatch,
+            Some(_) if !block.check_witness_commitment() => {
+                ValidationError::WitnessCommitmentMismatch
+            }
+            Some(height) => return Ok(height),
+        };
+        Err(Error::Validation { hash, err })
+    }
+
+    /// Fetches a block with `get_block` and checks it with [Self::verify_block].
+    pub async fn get_verified_block(&self, client: &Client, hash: &BlockHash) -> Result<Block> {
+        let block = client.get_block(hash).await?;
+        self.verify_block(&block)?;
+        Ok(block)
+    }
+
+    fn push(&mut self, header: Header) {
+        self.headers.push(header);
+        self.index.insert(header.block_hash(), self.tip_height());
+    }
+}
+
+/// The validated chain up to a fork point plus the headers of a candidate
+/// branch on top of it.
+struct Branch<'a> {
+    params: &'a Params,
+    base_height: u64,
+    trunk: &'a [Header],
+    extra: Vec<Header>,
+}
+
+impl Branch<'_> {
+    fn tip_height(&self) -> u64 {
+        self.base_height + (self.trunk.len() + self.extra.len()) as u64 - 1
+    }
+
+    fn get(&self, height: u64) -> Option<&Header> {
+        let idx = height.checked_sub(self.base_height)? as usize;
+        match idx.checked_sub(self.trunk.len()) {
+            None => self.trunk.get(idx),
+            Some(idx) => self.extra.get(idx),
+        }
+    }
+
+    /// Validates `header` as the successor of the branch tip.
+    fn check(&self, header: &Header) -> Result<()> {
+        let hash = header.block_hash();
+        self.check_inner(header)
+            .map_err(|err| Error::Validation { hash, err })
+    }
+
+    fn check_inner(&self, header: &Header) -> std::result::Result<(), ValidationError> {
+        let prev_height = self.tip_height();
+        let prev = self.get(prev_height).expect("branch is never empty");
+        if header.prev_blockhash != prev.block_hash() {
+            return Err(ValidationError::Disconnected(prev.block_hash()));
+        }
+
+        let expected = self.next_work_required(prev_height + 1, header.time);
+        if header.bits.to_consensus() != expected {
+            return Err(ValidationError::BadDifficultyBits {
+                expected,
+                found: header.bits.to_consensus(),
+            });
+        }
+        if header.target() > self.params.max_attainable_target
+            || header.validate_pow(header.target()).is_err()
+        {
+            return Err(ValidationError::InsufficientWork);
+        }
+
+        let median = self.median_time_past();
+        if header.time <= median {
+            return Err(ValidationError::TimeTooOld {
+                time: header.time,
+                median,
+            });
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        if u64::from(header.time) > now + u64::from(MAX_FUTURE_BLOCK_TIME) {
+            return Err(ValidationError::TimeTooNew { time: header.time });
+        }
+        Ok(())
+    }
+
+    fn median_time_past(&self) -> u32 {
+        let tip = self.tip_height();
+        let mut times: Vec<u32> = (0..MEDIAN_TIME_SPAN as u64)
+            .map_while(|i| tip.checked_sub(i).and_then(|h| self.get(h)))
+            .map(|h| h.time)
+            .collect();
+        times.sort_unstable();
+        times[times.len() / 2]
+    }
+
+    /// Mirrors `GetNextWorkRequired` from Bitcoin Core.
+    fn next_work_required(&self, height: u64, time: u32) -> u32 {
+        let params = self.params;
+        let interval = params.difficulty_adjustment_interval();
+        let prev = self.get(height - 1).expect("previous header is present");
+        let pow_limit = params
+            .max_attainable_target
+            .to_compact_lossy()
+            .to_consensus();
+
+        if height % interval != 0 {
+            if !params.allow_min_difficulty_blocks {
+                return prev.bits.to_consensus();
+            }
+            // Testnet special rule: a block more than twice the target
+            // spacing after its parent may be mined at minimum difficulty.
+            if u64::from(time) > u64::from(prev.time) + params.pow_target_spacing * 2 {
+                return pow_limit;
+            }
+            let mut h = height - 1;
+            while h % interval != 0 && h > self.base_height {
+                let bits = self.get(h).expect("header is present").bits.to_consensus();
+                if bits != pow_limit {
+                    return bits;
+                }
+                h -= 1;
+            }
+            return self.get(h).expect("header is present").bits.to_consensus();
+        }
+
+        if params.no_pow_retargeting {
+            return prev.bits.to_consensus();
+        }
+        let first = self
+            .get(height - interval)
+            .expect("period start is present");
+        let timespan = i64::from(prev.time) - i64::from(first.time);
+        retarget(params, prev.target(), timespan)
+            .to_compact_lossy()
+            .to_consensus()
+    }
+}
+
+/// Computes the target for a new difficulty period, as in Bitcoin Core's
+/// `CalculateNextWorkRequired`.
+fn retarget(params: &Params, last_target: Target, actual_timespan: i64) -> Target {
+    let target_timespan = params.pow_target_timespan;
+    let timespan = (actual_timespan.max(0) as u64).clamp(target_timespan / 4, target_timespan * 4);
+    let target = mul_div(last_target, timespan, target_timespan);
+    target.min(params.max_attainable_target)
+}
+
+/// Computes `target * mul / div` with 320-bit intermediate precision,
+/// saturating at the maximum 256-bit value.
+fn mul_div(target: Target, mul: u64, div: u64) -> Target {
+    let bytes = target.to_le_bytes();
+    let mut limbs = [0u64; 5];
+    for (i, chunk) in bytes.chunks(8).enumerate() {
+        limbs[i] = u64::from_le_bytes(chunk.try_into().expect("8 byte chunk"));
+    }
+
+    let mut carry = 0u128;
+    for limb in limbs.iter_mut() {
+        let v = u128::from(*limb) * u128::from(mul) + carry;
+        *limb = v as u64;
+        carry = v >> 64;
+    }
+
+    let mut rem = 0u128;
+    for limb in limbs.iter_mut().rev() {
+        let v = (rem << 64) | u128::from(*limb);
+        *limb = (v / u128::from(div)) as u64;
+        rem = v % u128::from(div);
+    }
+
+    if limbs[4] != 0 {
+        return Target::from_le_bytes([0xff; 32]);
+    }
+    let mut out = [0u8; 32];
+    for (i, limb) in limbs[..4].iter().enumerate() {
+        out[i * 8..(i + 1) * 8].copy_from_slice(&limb.to_le_bytes());
+    }
+    Target::from_le_bytes(out)
+}
+
+fn sum_work(headers: &[Header]) -> Option<Work> {
+    headers.iter().map(Header::work).reduce(|a, b| a + b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc_json::bitcoin::{block, hashes::Hash, CompactTarget, TxMerkleNode};
+
+    fn mine(prev: &Header, time: u32) -> Header {
+        let mut header = Header {
+            version: block::Version::TWO,
+            prev_blockhash: prev.block_hash(),
+            merkle_root: TxMerkleNode::all_zeros(),
+            time,
+            bits: prev.bits,
+            nonce: 0,
+        };
+        while header.validate_pow(header.target()).is_err() {
+            header.nonce += 1;
+        }
+        header
+    }
+
+    #[test]
+    fn connects_valid_regtest_headers() {
+        let mut verifier = HeaderVerifier::new(Network::Regtest);
+        for _ in 0..12 {
+            let tip = *verifier.tip();
+            verifier.connect(mine(&tip, tip.time + 600)).unwrap();
+        }
+        assert_eq!(verifier.tip_height(), 12);
+        let hash = verifier.header_at(7).unwrap().block_hash();
+        assert_eq!(verifier.height_of(&hash), Some(7));
+    }
+
+    #[test]
+    fn rejects_disconnected_header() {
+        let mut verifier = HeaderVerifier::new(Network::Regtest);
+        let genesis = *verifier.tip();
+        let first = mine(&genesis, genesis.time + 600);
+        let orphan = mine(&first, first.time + 600);
+        assert!(matches!(
+            verifier.connect(orphan),
+            Err(Error::Validation {
+                err: ValidationError::Disconnected(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_unexpected_bits() {
+        let mut verifier = HeaderVerifier::new(Network::Regtest);
+        let genesis = *verifier.tip();
+        let mut header = mine(&genesis, genesis.time + 600);
+        header.bits = CompactTarget::from_consensus(0x1d00ffff);
+        assert!(matches!(
+            verifier.connect(header),
+            Err(Error::Validation {
+                err: ValidationError::BadDifficultyBits { .. },
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_old_timestamp() {
+        let mut verifier = HeaderVerifier::new(Network::Regtest);
+        let genesis = *verifier.tip();
+        let header = mine(&genesis, genesis.time);
+        assert!(matches!(
+            verifier.connect(header),
+            Err(Error::Validation {
+                err: ValidationError::TimeTooOld { .. },
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_misaligned_checkpoint() {
+        let genesis = genesis_block(Network::Bitcoin).header;
+        assert!(HeaderVerifier::from_checkpoint(Network::Bitcoin, 2016, genesis).is_ok());
+        assert!(HeaderVerifier::from_checkpoint(Network::Bitcoin, 2017, genesis).is_err());
+    }
+
+    #[test]
+    fn retarget_follows_timespan() {
+        let params = Params::new(Network::Bitcoin);
+        let target = Target::from_compact(CompactTarget::from_consensus(0x1c7fff80));
+        let span = params.pow_target_timespan as i64;
+
+        let same = retarget(&params, target, span);
+        assert_eq!(same.to_compact_lossy().to_consensus(), 0x1c7fff80);
+
+        let easier = retarget(&params, target, span * 2);
+        assert_eq!(easier.to_compact_lossy().to_consensus(), 0x1d00ffff);
+
+        let harder = retarget(&params, target, span / 2);
+        assert_eq!(harder.to_compact_lossy().to_consensus(), 0x1c3fffc0);
+
+        let clamped = retarget(&params, target, span * 100);
+        assert_eq!(clamped, params.max_attainable_target);
+    }
+}
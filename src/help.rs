@@ -0,0 +1,114 @@
+use std::collections::BTreeMap;
+
+/// The commands a node supports, parsed from the output of `help`.
+///
+/// Hidden commands are not listed by `help` and so are not included.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct HelpIndex {
+    categories: BTreeMap<String, Vec<HelpEntry>>,
+}
+
+/// A command listed by `help`, with its usage summary.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct HelpEntry {
+    pub method: String,
+    /// The arguments as listed by `help`, e.g. `"blockhash" ( verbosity )`.
+    pub usage: String,
+}
+
+impl HelpIndex {
+    /// Parses the output of `help` called without a command.
+    ///
+    /// Commands are grouped under `== Category ==` lines; commands listed
+    /// before any category are grouped under an empty one.
+    pub fn parse(help: &str) -> Self {
+        let mut categories: BTreeMap<String, Vec<HelpEntry>> = BTreeMap::new();
+        let mut category = String::new();
+        for line in help.lines().map(str::trim) {
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix("==").and_then(|l| l.strip_suffix("==")) {
+                category = name.trim().to_owned();
+                continue;
+            }
+            let (method, usage) = line.split_once(' ').unwrap_or((line, ""));
+            categories
+                .entry(category.clone())
+                .or_default()
+                .push(HelpEntry {
+                    method: method.to_owned(),
+                    usage: usage.trim().to_owned(),
+                });
+        }
+        Self { categories }
+    }
+
+    /// Returns true if the node supports `method`.
+    pub fn supports(&self, method: &str) -> bool {
+        self.get(method).is_some()
+    }
+
+    /// Returns the entry of `method`.
+    pub fn get(&self, method: &str) -> Option<&HelpEntry> {
+        self.categories
+            .values()
+            .flatten()
+            .find(|e| e.method == method)
+    }
+
+    /// Returns the category `method` is listed under, e.g. "Blockchain".
+    pub fn category_of(&self, method: &str) -> Option<&str> {
+        self.categories
+            .iter()
+            .find(|(_, entries)| entries.iter().any(|e| e.method == method))
+            .map(|(category, _)| category.as_str())
+    }
+
+    /// Returns the categories and the commands listed under each.
+    pub fn categories(&self) -> &BTreeMap<String, Vec<HelpEntry>> {
+        &self.categories
+    }
+
+    /// Returns the names of all the listed commands.
+    pub fn methods(&self) -> impl Iterator<Item = &str> {
+        self.categories
+            .values()
+            .flatten()
+            .map(|e| e.method.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELP: &str = "== Blockchain ==
+getbestblockhash
+getblock \"blockhash\" ( verbosity )
+getchainstates
+
+== Control ==
+getmemoryinfo ( \"mode\" )
+logging ( [\"include_category\",...] [\"exclude_category\",...] )
+
+== Wallet ==
+abandontransaction \"txid\"
+";
+
+    #[test]
+    fn parses_help() {
+        let index = HelpIndex::parse(HELP);
+        assert_eq!(index.categories().len(), 3);
+        assert_eq!(index.methods().count(), 6);
+        assert!(index.supports("getchainstates"));
+        assert!(!index.supports("dumptxoutset"));
+        assert!(!index.supports("=="));
+        assert_eq!(index.category_of("logging"), Some("Control"));
+        assert_eq!(
+            index.get("getblock").unwrap().usage,
+            "\"blockhash\" ( verbosity )"
+        );
+        assert_eq!(index.get("getbestblockhash").unwrap().usage, "");
+    }
+}
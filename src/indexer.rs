@@ -0,0 +1,467 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bitcoincore_rpc_json::bitcoin::{
+    hashes::{hash_newtype, sha256, Hash},
+    Amount, Block, BlockHash, OutPoint, Script, ScriptBuf, Txid,
+};
+use tokio::{
+    task::JoinHandle,
+    time::{interval, MissedTickBehavior},
+};
+
+use crate::{
+    chain::{ChainEvent, ChainFollower, DEFAULT_MAX_REORG_DEPTH},
+    client::{Client, Result},
+    error::Error,
+};
+
+/// An index updated with the blocks connected to and disconnected from the
+/// best chain, in order, as maintained by an [Indexer].
+///
+/// Both fail with [Error::IndexOutOfSync] when the index cannot follow the
+/// change, in which case it must be rebuilt.
+pub trait BlockIndex: Send + Sync {
+    /// Applies `block`, connected at `height`.
+    fn block_connected(&self, height: u64, block: &Block) -> Result<()>;
+
+    /// Undoes the block with `hash` at `height`, disconnected by a reorg.
+    fn block_disconnected(&self, height: u64, hash: &BlockHash) -> Result<()>;
+}
+
+/// Maintains a [BlockIndex] by following the node's best chain and
+/// downloading the connected blocks in JSON-RPC batches.
+pub struct Indexer<I> {
+    client: Arc<Client>,
+    index: Arc<I>,
+    follower: ChainFollower,
+    batch_size: usize,
+    /// Events reported by the follower but not yet applied to the index.
+    pending: VecDeque<ChainEvent>,
+}
+
+impl<I: BlockIndex + 'static> Indexer<I> {
+    /// Creates an indexer applying the blocks after the last one processed
+    /// by `follower` to `index`, downloading 16 blocks per batch.
+    ///
+    /// Use a follower created with [ChainFollower::new] to index past
+    /// blocks, or with [ChainFollower::from_tip] to only index new ones.
+    pub fn new(client: Arc<Client>, index: Arc<I>, follower: ChainFollower) -> Self {
+        Self {
+            client,
+            index,
+            follower,
+            batch_size: 16,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Sets how many blocks are downloaded in a single batch.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn index(&self) -> &Arc<I> {
+        &self.index
+    }
+
+    /// Height and hash of the last block seen by the follower.
+    pub fn tip(&self) -> (u64, BlockHash) {
+        self.follower.tip()
+    }
+
+    /// Catches up with the node's best chain, returning the number of
+    /// blocks indexed.
+    ///
+    /// On error, the blocks not yet indexed are retried by the next call.
+    /// [Error::IndexOutOfSync] persists until the index is rebuilt.
+    pub async fn sync(&mut self) -> Result<usize> {
+        self.pending.extend(self.follower.poll(&self.client).await?);
+        let mut connected = 0;
+        while let Some(event) = self.pending.front() {
+            match *event {
+                ChainEvent::Disconnected { height, hash } => {
+                    self.index.block_disconnected(height, &hash)?;
+                    self.pending.pop_front();
+                }
+                ChainEvent::Connected { .. } => {
+                    let batch: Vec<(u64, BlockHash)> = self
+                        .pending
+                        .iter()
+                        .take(self.batch_size)
+                        .map_while(|e| match *e {
+                            ChainEvent::Connected { height, hash } => Some((height, hash)),
+                            ChainEvent::Disconnected { .. } => None,
+                        })
+                        .collect();
+                    let hashes: Vec<BlockHash> = batch.iter().map(|(_, hash)| *hash).collect();
+                    let blocks = self.client.get_blocks(&hashes).await?;
+                    for ((height, _), block) in batch.iter().zip(&blocks) {
+                        self.index.block_connected(*height, block)?;
+                        self.pending.pop_front();
+                        connected += 1;
+                    }
+                }
+            }
+        }
+        Ok(connected)
+    }
+
+    /// Calls [Self::sync] every `poll_interval` on a spawned task, until it
+    /// fails.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn run(mut self, poll_interval: Duration) -> JoinHandle<Result<()>> {
+        tokio::spawn(async move {
+            let mut ticker = interval(poll_interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                self.sync().await?;
+            }
+        })
+    }
+}
+
+hash_newtype! {
+    /// The SHA256 of an output script, which keys the index. Like in the
+    /// Electrum protocol, it is shown byte-reversed.
+    #[hash_newtype(backward)]
+    pub struct ScriptHash(sha256::Hash);
+}
+
+impl ScriptHash {
+    pub fn from_script(script: &Script) -> Self {
+        Self::hash(script.as_bytes())
+    }
+}
+
+/// A confirmed transaction paying to or spending from a script.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct HistoryEntry {
+    pub txid: Txid,
+    pub height: u64,
+}
+
+/// An unspent output paying to a script.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Utxo {
+    pub outpoint: OutPoint,
+    pub value: Amount,
+    /// Height of the block that confirmed the output.
+    pub height: u64,
+}
+
+/// An in-memory index of the confirmed history and unspent outputs of
+/// scripts, maintained by an [Indexer].
+///
+/// Either every script is indexed, or only those watched, which keeps the
+/// index small when a known set of scripts is queried. The changes made by
+/// the most recent blocks are remembered so that they can be undone on a
+/// reorg.
+pub struct AddressIndex {
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    /// The scripts indexed, or `None` for all of them.
+    watched: Option<HashSet<ScriptHash>>,
+    history: HashMap<ScriptHash, Vec<HistoryEntry>>,
+    utxos: HashMap<ScriptHash, BTreeMap<OutPoint, Utxo>>,
+    /// The script of every indexed unspent output, to find those spent.
+    outputs: HashMap<OutPoint, ScriptHash>,
+    undo: VecDeque<(BlockHash, BlockUndo)>,
+    max_reorg_depth: usize,
+}
+
+/// The changes made by a block to the index.
+#[derive(Default)]
+struct BlockUndo {
+    /// Scripts whose history got an entry, in order.
+    history: Vec<ScriptHash>,
+    created: Vec<OutPoint>,
+    spent: Vec<(ScriptHash, Utxo)>,
+}
+
+impl Default for AddressIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AddressIndex {
+    /// Creates an index of every script.
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                watched: None,
+                history: HashMap::new(),
+                utxos: HashMap::new(),
+                outputs: HashMap::new(),
+                undo: VecDeque::new(),
+                max_reorg_depth: DEFAULT_MAX_REORG_DEPTH,
+            }),
+        }
+    }
+
+    /// Creates an index of the `scripts` only.
+    pub fn watching(scripts: impl IntoIterator<Item = ScriptBuf>) -> Self {
+        let index = Self::new();
+        index.inner.lock().expect("poisoned").watched = Some(
+            scripts
+                .into_iter()
+                .map(|s| ScriptHash::from_script(&s))
+                .collect(),
+        );
+        index
+    }
+
+    /// Sets how many blocks can be undone on a reorg, which should match
+    /// the [ChainFollower] of the indexer.
+    pub fn max_reorg_depth(self, depth: usize) -> Self {
+        self.inner.lock().expect("poisoned").max_reorg_depth = depth.max(1);
+        self
+    }
+
+    /// Starts indexing `script`, if only watched scripts are indexed.
+    ///
+    /// Only the blocks connected from then on are indexed for it.
+    pub fn watch(&self, script: &Script) {
+        if let Some(watched) = &mut self.inner.lock().expect("poisoned").watched {
+            watched.insert(ScriptHash::from_script(script));
+        }
+    }
+
+    /// Returns the confirmed transactions paying to or spending from
+    /// `script`, oldest first.
+    pub fn history(&self, script: &Script) -> Vec<HistoryEntry> {
+        let inner = self.inner.lock().expect("poisoned");
+        let history = inner.history.get(&ScriptHash::from_script(script));
+        history.cloned().unwrap_or_default()
+    }
+
+    /// Returns the unspent outputs paying to `script`, oldest first.
+    pub fn utxos(&self, script: &Script) -> Vec<Utxo> {
+        let inner = self.inner.lock().expect("poisoned");
+        let mut utxos: Vec<Utxo> = inner
+            .utxos
+            .get(&ScriptHash::from_script(script))
+            .map(|u| u.values().copied().collect())
+            .unwrap_or_default();
+        utxos.sort_by_key(|u| u.height);
+        utxos
+    }
+
+    /// Returns the confirmed balance of `script`.
+    pub fn balance(&self, script: &Script) -> Amount {
+        let inner = self.inner.lock().expect("poisoned");
+        let utxos = inner.utxos.get(&ScriptHash::from_script(script));
+        utxos
+            .into_iter()
+            .flat_map(|u| u.values())
+            .map(|u| u.value)
+            .sum()
+    }
+}
+
+impl Inner {
+    fn push_history(&mut self, script: ScriptHash, entry: HistoryEntry, undo: &mut BlockUndo) {
+        let history = self.history.entry(script).or_default();
+        // A transaction is listed once, even when it has several inputs or
+        // outputs of the script.
+        if history.last() != Some(&entry) {
+            history.push(entry);
+            undo.history.push(script);
+        }
+    }
+}
+
+impl BlockIndex for AddressIndex {
+    fn block_connected(&self, height: u64, block: &Block) -> Result<()> {
+        let mut inner = self.inner.lock().expect("poisoned");
+        let hash = block.block_hash();
+        if inner
+            .undo
+            .back()
+            .is_some_and(|(tip, _)| *tip != block.header.prev_blockhash)
+        {
+            return Err(Error::IndexOutOfSync { hash });
+        }
+        let mut undo = BlockUndo::default();
+        for tx in &block.txdata {
+            let txid = tx.compute_txid();
+            let entry = HistoryEntry { txid, height };
+            if !tx.is_coinbase() {
+                for input in &tx.input {
+                    let Some(script) = inner.outputs.remove(&input.previous_output) else {
+                        continue;
+                    };
+                    let utxos = inner.utxos.get_mut(&script).expect("indexed output");
+                    let utxo = utxos.remove(&input.previous_output).expect("indexed");
+                    if utxos.is_empty() {
+                        inner.utxos.remove(&script);
+                    }
+                    inner.push_history(script, entry, &mut undo);
+                    undo.spent.push((script, utxo));
+                }
+            }
+            for (vout, output) in tx.output.iter().enumerate() {
+                if output.script_pubkey.is_op_return() {
+                    continue;
+                }
+                let script = ScriptHash::from_script(&output.script_pubkey);
+                if inner.watched.as_ref().is_some_and(|w| !w.contains(&script)) {
+                    continue;
+                }
+                let outpoint = OutPoint::new(txid, vout as u32);
+                inner.outputs.insert(outpoint, script);
+                inner.utxos.entry(script).or_default().insert(
+                    outpoint,
+                    Utxo {
+                        outpoint,
+                        value: output.value,
+                        height,
+                    },
+                );
+                inner.push_history(script, entry, &mut undo);
+                undo.created.push(outpoint);
+            }
+        }
+        inner.undo.push_back((hash, undo));
+        while inner.undo.len() > inner.max_reorg_depth {
+            inner.undo.pop_front();
+        }
+        Ok(())
+    }
+
+    fn block_disconnected(&self, _height: u64, hash: &BlockHash) -> Result<()> {
+        let mut inner = self.inner.lock().expect("poisoned");
+        // Blocks are disconnected from the tip down, and only the most recent
+        // ones can be undone.
+        if inner.undo.back().map(|(h, _)| h) != Some(hash) {
+            return Err(Error::IndexOutOfSync { hash: *hash });
+        }
+        let (_, undo) = inner.undo.pop_back().expect("checked");
+        for script in undo.history.iter().rev() {
+            let history = inner.history.get_mut(script).expect("indexed script");
+            history.pop();
+            if history.is_empty() {
+                inner.history.remove(script);
+            }
+        }
+        // Restore the spent outputs first, as some may have been created by
+        // the block itself.
+        for (script, utxo) in undo.spent {
+            inner.outputs.insert(utxo.outpoint, script);
+            inner
+                .utxos
+                .entry(script)
+                .or_default()
+                .insert(utxo.outpoint, utxo);
+        }
+        for outpoint in undo.created {
+            let script = inner.outputs.remove(&outpoint).expect("indexed output");
+            let utxos = inner.utxos.get_mut(&script).expect("indexed output");
+            utxos.remove(&outpoint);
+            if utxos.is_empty() {
+                inner.utxos.remove(&script);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc_json::bitcoin::{
+        absolute::LockTime, block, transaction, CompactTarget, Sequence, Transaction, TxIn,
+        TxMerkleNode, TxOut, Witness,
+    };
+
+    fn block_with(prev: BlockHash, txdata: Vec<Transaction>) -> Block {
+        Block {
+            header: block::Header {
+                version: block::Version::TWO,
+                prev_blockhash: prev,
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: 0,
+                bits: CompactTarget::from_consensus(0x207fffff),
+                nonce: 0,
+            },
+            txdata,
+        }
+    }
+
+    fn tx(spends: &[OutPoint], pays: &[(&ScriptBuf, u64)]) -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: spends
+                .iter()
+                .map(|&previous_output| TxIn {
+                    previous_output,
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: pays
+                .iter()
+                .map(|&(script, sat)| TxOut {
+                    value: Amount::from_sat(sat),
+                    script_pubkey: script.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn indexes_history_and_utxos_across_reorgs() {
+        let alice = ScriptBuf::from_bytes(vec![0x51]);
+        let bob = ScriptBuf::from_bytes(vec![0x52]);
+        let carol = ScriptBuf::from_bytes(vec![0x53]);
+        let index = AddressIndex::watching([alice.clone(), bob.clone()]);
+
+        let funding = tx(&[], &[(&alice, 5_000), (&alice, 3_000), (&carol, 1)]);
+        let funded = funding.compute_txid();
+        let first = block_with(BlockHash::all_zeros(), vec![funding]);
+        index.block_connected(1, &first).unwrap();
+        assert_eq!(index.balance(&alice), Amount::from_sat(8_000));
+        assert_eq!(index.history(&alice).len(), 1);
+        assert!(index.history(&carol).is_empty());
+
+        let spend = tx(&[OutPoint::new(funded, 0)], &[(&bob, 4_000)]);
+        let spent = spend.compute_txid();
+        let tip = block_with(first.block_hash(), vec![spend]);
+        assert!(index.block_connected(2, &first).is_err());
+        index.block_connected(2, &tip).unwrap();
+        assert_eq!(index.balance(&alice), Amount::from_sat(3_000));
+        assert_eq!(index.utxos(&alice)[0].outpoint, OutPoint::new(funded, 1));
+        assert_eq!(
+            index.history(&alice),
+            [
+                HistoryEntry {
+                    txid: funded,
+                    height: 1
+                },
+                HistoryEntry {
+                    txid: spent,
+                    height: 2
+                },
+            ]
+        );
+        assert_eq!(index.utxos(&bob)[0].value, Amount::from_sat(4_000));
+
+        assert!(index.block_disconnected(1, &first.block_hash()).is_err());
+        index.block_disconnected(2, &tip.block_hash()).unwrap();
+        assert_eq!(index.balance(&alice), Amount::from_sat(8_000));
+        assert_eq!(index.history(&alice).len(), 1);
+        assert!(index.history(&bob).is_empty());
+        assert_eq!(index.balance(&bob), Amount::ZERO);
+    }
+}
@@ -65,6 +65,12 @@ pub enum ResponseData<R> {
     Success { result: R },
 }
 
+/// A response carrying an error, whatever the HTTP status it came with.
+#[derive(Deserialize, Debug)]
+pub struct ErrorResponse {
+    pub error: JsonRpcError,
+}
+
 impl<R> ResponseData<R> {
     /// Consume response and return value
     pub fn into_result(self) -> Result<R, JsonRpcError> {
@@ -1,7 +1,33 @@
+pub mod broadcast;
+pub mod bumper;
+pub mod cache;
+pub mod chain;
 pub mod client;
+pub mod coin_selection;
+pub mod compat;
 pub mod error;
+pub mod fees;
+pub mod filters;
+pub mod headers;
+pub mod help;
+pub mod indexer;
 mod jsonrpc;
+pub mod merkle;
+pub mod message;
+pub mod mining;
+pub mod payments;
+mod progress;
 mod relay;
+pub mod rescan;
+pub mod scan;
+pub mod snapshot;
+pub mod stats;
+#[cfg(feature = "store")]
+pub mod store;
+pub mod tx_builder;
+pub mod txindex;
+pub mod types;
+pub mod unlock;
 
 pub use bitcoincore_rpc;
 pub use bitcoincore_rpc_json;
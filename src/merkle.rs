@@ -0,0 +1,96 @@
+use bitcoincore_rpc_json::bitcoin::{
+    block::Header, consensus::encode, merkle_tree::MerkleBlock, Txid,
+};
+
+use crate::{client::Result, error::Error};
+
+/// Decodes a serialized `MerkleBlock`, as returned by `gettxoutproof`.
+pub fn decode_tx_out_proof(proof: &[u8]) -> Result<MerkleBlock> {
+    Ok(encode::deserialize(proof)?)
+}
+
+/// Verifies a merkle proof against a header obtained from a trusted source.
+///
+/// Checks that the proof commits to `header` and that its partial merkle tree
+/// hashes up to the header's merkle root. Returns the txids proven to be
+/// included in the block.
+pub fn verify_merkle_block(merkle_block: &MerkleBlock, header: &Header) -> Result<Vec<Txid>> {
+    if merkle_block.header != *header {
+        return Err(Error::HeaderMismatch {
+            expected: header.block_hash(),
+            found: merkle_block.header.block_hash(),
+        });
+    }
+
+    let mut matches = Vec::new();
+    let mut indexes = Vec::new();
+    merkle_block.extract_matches(&mut matches, &mut indexes)?;
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc_json::bitcoin::{
+        block, hashes::Hash, BlockHash, CompactTarget, TxMerkleNode,
+    };
+
+    fn txids() -> Vec<Txid> {
+        (1..=5u8).map(|i| Txid::from_byte_array([i; 32])).collect()
+    }
+
+    fn header_for(txids: &[Txid]) -> Header {
+        let merkle_root =
+            bitcoincore_rpc_json::bitcoin::merkle_tree::calculate_root(txids.iter().copied())
+                .map(|h: Txid| TxMerkleNode::from_byte_array(h.to_byte_array()))
+                .unwrap();
+        Header {
+            version: block::Version::TWO,
+            prev_blockhash: BlockHash::all_zeros(),
+            merkle_root,
+            time: 1_700_000_000,
+            bits: CompactTarget::from_consensus(0x207fffff),
+            nonce: 0,
+        }
+    }
+
+    #[test]
+    fn verifies_included_txids() {
+        let txids = txids();
+        let header = header_for(&txids);
+        let wanted = [txids[1], txids[4]];
+        let mb =
+            MerkleBlock::from_header_txids_with_predicate(&header, &txids, |t| wanted.contains(t));
+
+        let proof = encode::serialize(&mb);
+        let decoded = decode_tx_out_proof(&proof).unwrap();
+        assert_eq!(verify_merkle_block(&decoded, &header).unwrap(), wanted);
+    }
+
+    #[test]
+    fn rejects_foreign_header() {
+        let txids = txids();
+        let header = header_for(&txids);
+        let mb = MerkleBlock::from_header_txids_with_predicate(&header, &txids, |t| *t == txids[0]);
+
+        let mut other = header;
+        other.nonce = 1;
+        assert!(matches!(
+            verify_merkle_block(&mb, &other),
+            Err(Error::HeaderMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_tampered_root() {
+        let txids = txids();
+        let mut header = header_for(&txids);
+        header.merkle_root = TxMerkleNode::all_zeros();
+        let mb = MerkleBlock::from_header_txids_with_predicate(&header, &txids, |t| *t == txids[0]);
+
+        assert!(matches!(
+            verify_merkle_block(&mb, &header),
+            Err(Error::MerkleBlock(_))
+        ));
+    }
+}
@@ -0,0 +1,146 @@
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use bitcoincore_rpc_json::bitcoin::{
+    secp256k1::{
+        ecdsa::{RecoverableSignature, RecoveryId},
+        Secp256k1,
+    },
+    sign_message::{signed_msg_hash, MessageSignature, MessageSignatureError},
+    Address, AddressType, ScriptBuf,
+};
+
+use crate::client::Result;
+
+/// The kind of key and address a BIP137 signature header declares.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SignatureKind {
+    /// Header 27-30: P2PKH with an uncompressed key.
+    P2pkhUncompressed,
+    /// Header 31-34: P2PKH with a compressed key, as produced by
+    /// `signmessage`.
+    P2pkh,
+    /// Header 35-38: P2SH-wrapped P2WPKH.
+    P2shP2wpkh,
+    /// Header 39-42: native P2WPKH.
+    P2wpkh,
+}
+
+/// Decodes a base64 message signature, as returned by `signmessage`,
+/// including the BIP137 headers for segwit addresses.
+pub fn decode_signature(
+    signature: &str,
+) -> std::result::Result<(MessageSignature, SignatureKind), MessageSignatureError> {
+    let bytes = BASE64_STANDARD
+        .decode(signature.trim())
+        .map_err(|_| MessageSignatureError::InvalidBase64)?;
+    if bytes.len() != 65 {
+        return Err(MessageSignatureError::InvalidLength);
+    }
+    let kind = match bytes[0] {
+        27..=30 => SignatureKind::P2pkhUncompressed,
+        31..=34 => SignatureKind::P2pkh,
+        35..=38 => SignatureKind::P2shP2wpkh,
+        39..=42 => SignatureKind::P2wpkh,
+        _ => {
+            return Err(MessageSignatureError::InvalidEncoding(
+                bitcoincore_rpc_json::bitcoin::secp256k1::Error::InvalidRecoveryId,
+            ))
+        }
+    };
+    let recid = RecoveryId::from_i32(((bytes[0] - 27) & 0x03) as i32)?;
+    let signature = RecoverableSignature::from_compact(&bytes[1..], recid)?;
+    let compressed = kind != SignatureKind::P2pkhUncompressed;
+    Ok((MessageSignature::new(signature, compressed), kind))
+}
+
+/// Checks offline that `signature` signs `message` for `address`, like
+/// `verifymessage` does.
+///
+/// Besides P2PKH, P2SH-P2WPKH and P2WPKH addresses are supported as in
+/// BIP137. Since many wallets sign for segwit addresses with a P2PKH header,
+/// any compressed-key header is accepted for them; the key recovered from the
+/// signature must still match the address.
+pub fn verify_message(address: &Address, signature: &str, message: &str) -> Result<bool> {
+    let (signature, kind) = decode_signature(signature)?;
+    let pubkey =
+        signature.recover_pubkey(&Secp256k1::verification_only(), signed_msg_hash(message))?;
+
+    let expected = match address.address_type() {
+        Some(AddressType::P2pkh) => ScriptBuf::new_p2pkh(&pubkey.pubkey_hash()),
+        Some(AddressType::P2wpkh) | Some(AddressType::P2sh) if !pubkey.compressed => {
+            return Ok(false)
+        }
+        Some(AddressType::P2wpkh) => {
+            ScriptBuf::new_p2wpkh(&pubkey.wpubkey_hash().expect("key is compressed"))
+        }
+        Some(AddressType::P2sh) => {
+            let redeem_script =
+                ScriptBuf::new_p2wpkh(&pubkey.wpubkey_hash().expect("key is compressed"));
+            ScriptBuf::new_p2sh(&redeem_script.script_hash())
+        }
+        Some(other) => return Err(MessageSignatureError::UnsupportedAddressType(other).into()),
+        None => return Ok(false),
+    };
+    // A segwit header does not prove ownership of a P2PKH address.
+    if address.address_type() == Some(AddressType::P2pkh)
+        && matches!(kind, SignatureKind::P2shP2wpkh | SignatureKind::P2wpkh)
+    {
+        return Ok(false);
+    }
+    Ok(address.script_pubkey() == expected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc_json::bitcoin::{
+        hashes::Hash,
+        secp256k1::{Message, SecretKey},
+        CompressedPublicKey, Network,
+    };
+
+    fn sign(secret: &SecretKey, message: &str, header_base: u8) -> String {
+        let secp = Secp256k1::new();
+        let msg = Message::from_digest(signed_msg_hash(message).to_byte_array());
+        let (recid, compact) = secp
+            .sign_ecdsa_recoverable(&msg, secret)
+            .serialize_compact();
+        let mut bytes = vec![header_base + recid.to_i32() as u8];
+        bytes.extend(compact);
+        BASE64_STANDARD.encode(bytes)
+    }
+
+    #[test]
+    fn verifies_bip137_signatures() {
+        let secp = Secp256k1::new();
+        let secret = SecretKey::from_slice(&[0x11; 32]).unwrap();
+        let key = CompressedPublicKey(secret.public_key(&secp));
+        let p2pkh = Address::p2pkh(key, Network::Bitcoin);
+        let p2wpkh = Address::p2wpkh(&key, Network::Bitcoin);
+        let p2sh = Address::p2shwpkh(&key, Network::Bitcoin);
+
+        let sig = sign(&secret, "proof of reserves", 31);
+        assert!(verify_message(&p2pkh, &sig, "proof of reserves").unwrap());
+        assert!(!verify_message(&p2pkh, &sig, "another message").unwrap());
+        // Electrum style: segwit address, P2PKH header.
+        assert!(verify_message(&p2wpkh, &sig, "proof of reserves").unwrap());
+
+        let sig = sign(&secret, "proof of reserves", 39);
+        assert_eq!(decode_signature(&sig).unwrap().1, SignatureKind::P2wpkh);
+        assert!(verify_message(&p2wpkh, &sig, "proof of reserves").unwrap());
+        assert!(!verify_message(&p2pkh, &sig, "proof of reserves").unwrap());
+
+        let sig = sign(&secret, "proof of reserves", 35);
+        assert!(verify_message(&p2sh, &sig, "proof of reserves").unwrap());
+
+        let other = SecretKey::from_slice(&[0x22; 32]).unwrap();
+        let sig = sign(&other, "proof of reserves", 39);
+        assert!(!verify_message(&p2wpkh, &sig, "proof of reserves").unwrap());
+    }
+
+    #[test]
+    fn rejects_malformed_signatures() {
+        assert!(decode_signature("not base64!").is_err());
+        assert!(decode_signature(&BASE64_STANDARD.encode([31; 10])).is_err());
+        assert!(decode_signature(&BASE64_STANDARD.encode([43; 65])).is_err());
+    }
+}
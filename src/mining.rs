@@ -0,0 +1,530 @@
+use std::sync::Arc;
+
+use bitcoincore_rpc::Error::UnexpectedStructure;
+use bitcoincore_rpc_json::{
+    self as json,
+    bitcoin::{
+        absolute::LockTime,
+        block::{Header, Version},
+        consensus::encode::{self, VarInt},
+        hashes::Hash,
+        opcodes::OP_0,
+        script::{Builder, PushBytesBuf},
+        transaction, Amount, Block, CompactTarget, OutPoint, ScriptBuf, Sequence, Transaction,
+        TxIn, TxMerkleNode, TxOut, Witness,
+    },
+};
+
+use crate::{
+    client::{Client, Result},
+    error::Error,
+};
+
+/// Builds the coinbase of the block at `height`, paying `value` to `payout`.
+///
+/// The script sig pushes the height as required by BIP34, followed by
+/// `extra`, or `OP_0` if `extra` is empty. Set `segwit` to add the witness
+/// reserved value; the commitment itself is added by
+/// [add_witness_commitment] once the block transactions are known.
+pub fn coinbase(
+    height: u64,
+    extra: &[u8],
+    payout: ScriptBuf,
+    value: Amount,
+    segwit: bool,
+) -> Transaction {
+    let builder = Builder::new().push_int(height as i64);
+    let script_sig = match PushBytesBuf::try_from(extra.to_vec()) {
+        Ok(extra) if !extra.is_empty() => builder.push_slice(extra),
+        _ => builder.push_opcode(OP_0),
+    }
+    .into_script();
+    let witness = if segwit {
+        Witness::from_slice(&[[0u8; 32]])
+    } else {
+        Witness::new()
+    };
+    Transaction {
+        version: transaction::Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig,
+            sequence: Sequence::MAX,
+            witness,
+        }],
+        output: vec![TxOut {
+            value,
+            script_pubkey: payout,
+        }],
+    }
+}
+
+/// Appends the BIP141 witness commitment of the block transactions to the
+/// coinbase, whose input must hold the witness reserved value.
+pub fn add_witness_commitment(block: &mut Block) {
+    let Some(witness_root) = block.witness_root() else {
+        return;
+    };
+    let reserved = block.txdata[0].input[0]
+        .witness
+        .nth(0)
+        .map(<[u8]>::to_vec)
+        .unwrap_or_default();
+    let commitment = Block::compute_witness_commitment(&witness_root, &reserved);
+    let mut data = PushBytesBuf::from([0xaa, 0x21, 0xa9, 0xed]);
+    data.extend_from_slice(commitment.as_ref())
+        .expect("36 bytes fit a push");
+    block.txdata[0].output.push(TxOut {
+        value: Amount::ZERO,
+        script_pubkey: ScriptBuf::new_op_return(data),
+    });
+}
+
+/// Searches for a nonce meeting the header target, rolling the time forward
+/// whenever the nonce space is exhausted.
+///
+/// Only practical at regtest difficulty.
+pub fn grind(header: &mut Header) {
+    let target = header.target();
+    while header.validate_pow(target).is_err() {
+        header.nonce = header.nonce.wrapping_add(1);
+        if header.nonce == 0 {
+            header.time += 1;
+        }
+    }
+}
+
+/// Decodes the compact target of `template`.
+fn template_bits(template: &json::GetBlockTemplateResult) -> Result<CompactTarget> {
+    let bits: [u8; 4] = template
+        .bits
+        .as_slice()
+        .try_into()
+        .map_err(|_| UnexpectedStructure)?;
+    Ok(CompactTarget::from_consensus(u32::from_be_bytes(bits)))
+}
+
+/// Builds a block on top of a `getblocktemplate` template, for hand-crafted
+/// regtest blocks.
+///
+/// The block holds the chosen transactions, in the order they were added,
+/// after the template transactions if [BlockBuilder::include_mempool] is
+/// set. The coinbase claims the subsidy, plus the fees of the template
+/// transactions when included.
+pub struct BlockBuilder<'a> {
+    client: &'a Client,
+    template: json::GetBlockTemplateResult,
+    payout: ScriptBuf,
+    include_mempool: bool,
+    transactions: Vec<Transaction>,
+    coinbase_extra: Vec<u8>,
+    time: Option<u32>,
+}
+
+impl<'a> BlockBuilder<'a> {
+    /// Fetches a template from the node to build a block paying `payout`.
+    pub async fn new(client: &'a Client, payout: ScriptBuf) -> Result<Self> {
+        let template = client
+            .get_block_template(
+                json::GetBlockTemplateModes::Template,
+                &[json::GetBlockTemplateRules::SegWit],
+                &[],
+            )
+            .await?;
+        Ok(Self::from_template(client, template, payout))
+    }
+
+    /// Builds a block on top of `template`, paying `payout`.
+    pub fn from_template(
+        client: &'a Client,
+        template: json::GetBlockTemplateResult,
+        payout: ScriptBuf,
+    ) -> Self {
+        Self {
+            client,
+            template,
+            payout,
+            include_mempool: false,
+            transactions: Vec::new(),
+            coinbase_extra: Vec::new(),
+            time: None,
+        }
+    }
+
+    /// Includes the mempool transactions the template selected.
+    pub fn include_mempool(mut self, include: bool) -> Self {
+        self.include_mempool = include;
+        self
+    }
+
+    /// Adds `tx`, after the transactions added so far.
+    pub fn add_transaction(mut self, tx: Transaction) -> Self {
+        self.transactions.push(tx);
+        self
+    }
+
+    /// Pushes `extra` after the height in the coinbase script sig, e.g. to
+    /// make two blocks at the same height differ.
+    pub fn coinbase_extra(mut self, extra: Vec<u8>) -> Self {
+        self.coinbase_extra = extra;
+        self
+    }
+
+    /// Sets the block time, the template "curtime" unless set.
+    pub fn time(mut self, time: u32) -> Self {
+        self.time = Some(time);
+        self
+    }
+
+    /// Builds the block and grinds its nonce.
+    pub fn build(&self) -> Result<Block> {
+        let template = &self.template;
+        let fees: Amount = template.transactions.iter().map(|tx| tx.fee).sum();
+        let value = if self.include_mempool {
+            template.coinbase_value
+        } else {
+            template.coinbase_value - fees
+        };
+        let segwit = !template.default_witness_commitment.is_empty();
+        let mut txdata = vec![coinbase(
+            template.height,
+            &self.coinbase_extra,
+            self.payout.clone(),
+            value,
+            segwit,
+        )];
+        if self.include_mempool {
+            for tx in &template.transactions {
+                txdata.push(tx.transaction()?);
+            }
+        }
+        txdata.extend(self.transactions.iter().cloned());
+
+        let mut block = Block {
+            header: Header {
+                version: Version::from_consensus(template.version as i32),
+                prev_blockhash: template.previous_block_hash,
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: self.time.unwrap_or(template.current_time as u32),
+                bits: template_bits(template)?,
+                nonce: 0,
+            },
+            txdata,
+        };
+        if segwit {
+            add_witness_commitment(&mut block);
+        }
+        block.header.merkle_root = block.compute_merkle_root().expect("block has a coinbase");
+        grind(&mut block.header);
+        Ok(block)
+    }
+
+    /// Builds the block and submits it to the node.
+    pub async fn submit(&self) -> Result<Block> {
+        let block = self.build()?;
+        self.client.submit_block(&block).await?;
+        Ok(block)
+    }
+}
+
+/// Bounds of the extranonce space reserved in the coinbase script sig.
+const MIN_EXTRANONCE_SIZE: usize = 1;
+const MAX_EXTRANONCE_SIZE: usize = 32;
+
+/// A block template ready to be mined, with its coinbase.
+///
+/// The coinbase claims the whole template "coinbasevalue" and reserves
+/// extranonce space at the end of its script sig, so that miners can vary it
+/// without rebuilding the template.
+#[derive(Clone, Debug)]
+pub struct Work {
+    pub template: json::GetBlockTemplateResult,
+    /// The template transactions, decoded.
+    pub transactions: Vec<Transaction>,
+    bits: CompactTarget,
+    coinbase: Transaction,
+    extranonce_size: usize,
+}
+
+impl Work {
+    /// Prepares `template` for mining, paying to `payout` and reserving
+    /// `extranonce_size` bytes, between 1 and 32.
+    pub fn new(
+        template: json::GetBlockTemplateResult,
+        payout: ScriptBuf,
+        extranonce_size: usize,
+    ) -> Result<Self> {
+        check_extranonce_size(extranonce_size)?;
+        let transactions = template
+            .transactions
+            .iter()
+            .map(|tx| tx.transaction())
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let segwit = !template.default_witness_commitment.is_empty();
+        let mut coinbase = coinbase(
+            template.height,
+            &vec![0; extranonce_size],
+            payout,
+            template.coinbase_value,
+            segwit,
+        );
+        // The commitment does not depend on the coinbase, so the one the node
+        // computed for its own coinbase holds for ours.
+        if segwit {
+            coinbase.output.push(TxOut {
+                value: Amount::ZERO,
+                script_pubkey: template.default_witness_commitment.clone(),
+            });
+        }
+        Ok(Self {
+            bits: template_bits(&template)?,
+            template,
+            transactions,
+            coinbase,
+            extranonce_size,
+        })
+    }
+
+    /// Returns the coinbase with `extranonce`, which must be as long as the
+    /// reserved space.
+    pub fn coinbase(&self, extranonce: &[u8]) -> Result<Transaction> {
+        if extranonce.len() != self.extranonce_size {
+            return Err(Error::ExtranonceMismatch {
+                expected: self.extranonce_size,
+                found: extranonce.len(),
+            });
+        }
+        let mut coinbase = self.coinbase.clone();
+        let mut script_sig = coinbase.input[0].script_sig.to_bytes();
+        let start = script_sig.len() - self.extranonce_size;
+        script_sig[start..].copy_from_slice(extranonce);
+        coinbase.input[0].script_sig = ScriptBuf::from_bytes(script_sig);
+        Ok(coinbase)
+    }
+
+    /// Splits the coinbase serialized without witness around the extranonce,
+    /// as the "coinb1" and "coinb2" of Stratum jobs.
+    pub fn coinbase_parts(&self) -> (Vec<u8>, Vec<u8>) {
+        let mut coinbase = self.coinbase.clone();
+        coinbase.input[0].witness.clear();
+        let bytes = encode::serialize(&coinbase);
+        let script_len = coinbase.input[0].script_sig.len();
+        // Version, input count and previous output precede the script sig.
+        let end = 4 + 1 + 36 + VarInt(script_len as u64).size() + script_len;
+        (
+            bytes[..end - self.extranonce_size].to_vec(),
+            bytes[end..].to_vec(),
+        )
+    }
+
+    /// Assembles the block for `extranonce`, `time` and `nonce`.
+    ///
+    /// `time` must lie between the template "mintime" and two hours past the
+    /// node clock; the template "curtime" is a safe choice.
+    pub fn block(&self, extranonce: &[u8], time: u32, nonce: u32) -> Result<Block> {
+        let mut txdata = Vec::with_capacity(self.transactions.len() + 1);
+        txdata.push(self.coinbase(extranonce)?);
+        txdata.extend(self.transactions.iter().cloned());
+        let mut block = Block {
+            header: Header {
+                version: Version::from_consensus(self.template.version as i32),
+                prev_blockhash: self.template.previous_block_hash,
+                merkle_root: TxMerkleNode::all_zeros(),
+                time,
+                bits: self.bits,
+                nonce,
+            },
+            txdata,
+        };
+        block.header.merkle_root = block.compute_merkle_root().expect("block has a coinbase");
+        Ok(block)
+    }
+}
+
+fn check_extranonce_size(size: usize) -> Result<()> {
+    if !(MIN_EXTRANONCE_SIZE..=MAX_EXTRANONCE_SIZE).contains(&size) {
+        return Err(Error::InvalidExtranonceSize {
+            size,
+            min: MIN_EXTRANONCE_SIZE,
+            max: MAX_EXTRANONCE_SIZE,
+        });
+    }
+    Ok(())
+}
+
+/// Supplies mining work from long-polled `getblocktemplate` templates.
+///
+/// Each call to [TemplateProvider::next] returns once the node has a new
+/// template, after the tip changed or transactions entered the mempool.
+pub struct TemplateProvider {
+    client: Arc<Client>,
+    payout: ScriptBuf,
+    extranonce_size: usize,
+    rules: Vec<json::GetBlockTemplateRules>,
+    longpollid: Option<String>,
+}
+
+impl TemplateProvider {
+    /// Creates a provider of work paying to `payout`, with 8 bytes of
+    /// extranonce and the segwit rules.
+    pub fn new(client: Arc<Client>, payout: ScriptBuf) -> Self {
+        Self {
+            client,
+            payout,
+            extranonce_size: 8,
+            rules: vec![json::GetBlockTemplateRules::SegWit],
+            longpollid: None,
+        }
+    }
+
+    /// Sets the extranonce space reserved in the coinbase, between 1 and 32
+    /// bytes.
+    pub fn extranonce_size(mut self, size: usize) -> Result<Self> {
+        check_extranonce_size(size)?;
+        self.extranonce_size = size;
+        Ok(self)
+    }
+
+    /// Sets the rules the templates are requested with, e.g. to add the
+    /// signet rules.
+    pub fn rules(mut self, rules: Vec<json::GetBlockTemplateRules>) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// Returns the current template the first time, then waits for a new
+    /// one.
+    pub async fn next(&mut self) -> Result<Work> {
+        let template = match &self.longpollid {
+            None => {
+                self.client
+                    .get_block_template(json::GetBlockTemplateModes::Template, &self.rules, &[])
+                    .await?
+            }
+            Some(longpollid) => {
+                self.client
+                    .get_block_template_longpoll(&self.rules, longpollid)
+                    .await?
+            }
+        };
+        self.longpollid = Some(template.longpollid.clone());
+        Work::new(template, self.payout.clone(), self.extranonce_size)
+    }
+
+    /// Checks `block` in proposal mode, then submits it.
+    ///
+    /// Fails with [Error::BlockRejected] if the proposal is refused, in
+    /// which case the block is not submitted.
+    pub async fn submit(&self, block: &Block) -> Result<()> {
+        if let Some(reason) = self.client.propose_block(block).await? {
+            return Err(Error::BlockRejected {
+                hash: block.block_hash(),
+                reason,
+            });
+        }
+        self.client.submit_block(block).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::Auth;
+    use bitcoincore_rpc_json::bitcoin::Txid;
+    use serde_json::json;
+
+    fn regtest_template() -> json::GetBlockTemplateResult {
+        serde_json::from_value(json!({
+            "bits": "207fffff",
+            "previousblockhash": "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206",
+            "curtime": 1700000000,
+            "height": 101,
+            "sigoplimit": 80000,
+            "sizelimit": 4000000,
+            "weightlimit": 4000000,
+            "version": 0x20000000,
+            "rules": ["csv", "!segwit", "taproot"],
+            "capabilities": ["proposal"],
+            "vbavailable": {},
+            "vbrequired": 0,
+            "longpollid": "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e22060",
+            "transactions": [],
+            "default_witness_commitment": "6a24aa21a9ede2f61c3f71d1defd3fa999dfa36953755c690689799962b48bebd836974e8cf9",
+            "coinbaseaux": {},
+            "coinbasevalue": 5000000000u64,
+            "target": "7fffff0000000000000000000000000000000000000000000000000000000000",
+            "mintime": 1699999000,
+            "mutable": ["time", "transactions", "prevblock"],
+            "noncerange": "00000000ffffffff",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn builds_valid_regtest_blocks() {
+        let template = regtest_template();
+        let spend = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::all_zeros(), 0),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::from_slice(&[[1u8; 72]]),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(1000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+
+        let client = Client::new("http://127.0.0.1:18443", Auth::None).unwrap();
+        let block = BlockBuilder::from_template(&client, template, ScriptBuf::new_op_return([]))
+            .add_transaction(spend)
+            .coinbase_extra(vec![7])
+            .build()
+            .unwrap();
+        assert_eq!(block.txdata.len(), 2);
+        assert_eq!(block.bip34_block_height().unwrap(), 101);
+        assert_eq!(block.txdata[0].output[0].value, Amount::from_int_btc(50));
+        assert!(block.check_merkle_root());
+        assert!(block.check_witness_commitment());
+        assert!(block.header.validate_pow(block.header.target()).is_ok());
+    }
+
+    #[test]
+    fn splits_coinbase_around_extranonce() {
+        assert!(matches!(
+            Work::new(regtest_template(), ScriptBuf::new_op_return([]), 0),
+            Err(Error::InvalidExtranonceSize {
+                size: 0,
+                min: 1,
+                max: 32
+            })
+        ));
+        let work = Work::new(regtest_template(), ScriptBuf::new_op_return([]), 4).unwrap();
+        let extranonce = [0xde, 0xad, 0xbe, 0xef];
+        let coinbase = work.coinbase(&extranonce).unwrap();
+        assert!(matches!(
+            work.coinbase(&extranonce[1..]),
+            Err(Error::ExtranonceMismatch {
+                expected: 4,
+                found: 3
+            })
+        ));
+        assert_eq!(coinbase.output.len(), 2);
+        assert_eq!(coinbase.output[0].value, Amount::from_int_btc(50));
+
+        let (coinb1, coinb2) = work.coinbase_parts();
+        let stripped = [coinb1, extranonce.to_vec(), coinb2].concat();
+        let decoded: Transaction = encode::deserialize(&stripped).unwrap();
+        assert_eq!(decoded.compute_txid(), coinbase.compute_txid());
+
+        let mut block = work.block(&extranonce, 1700000000, 0).unwrap();
+        assert_eq!(block.bip34_block_height().unwrap(), 101);
+        assert!(block.check_merkle_root());
+        grind(&mut block.header);
+        assert!(block.header.validate_pow(block.header.target()).is_ok());
+    }
+}
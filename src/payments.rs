@@ -0,0 +1,749 @@
+use std::{
+    collections::{HashMap, HashSet},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use bitcoincore_rpc_json::{
+    bitcoin::{
+        address::NetworkUnchecked, consensus::encode, Address, Amount, Block, BlockHash, OutPoint,
+        ScriptBuf, Transaction, Txid,
+    },
+    GetTransactionResultDetailCategory, ImportDescriptors, ListTransactionResult, Timestamp,
+};
+use futures_core::Stream;
+use serde_json::Value;
+use tokio::{
+    sync::mpsc,
+    task::JoinHandle,
+    time::{interval, MissedTickBehavior},
+};
+
+use crate::{
+    chain::{ChainEvent, ChainFollower},
+    client::{Client, Result},
+    error::Error,
+    jsonrpc::JsonRpcError,
+};
+
+/// Confirmations after which a payment is reported for the last time by
+/// default.
+pub const DEFAULT_CONFIRMATIONS: u32 = 6;
+
+/// Where a payment stands.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PaymentStatus {
+    /// The paying transaction is unconfirmed, either new in the mempool or
+    /// returned to it by a reorg.
+    Pending,
+    /// The paying transaction is in the best chain.
+    Confirmed { block_hash: BlockHash, height: u64 },
+    /// The paying transaction was replaced, conflicted or evicted.
+    Removed,
+}
+
+/// An output paying one of the watched scripts.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Payment {
+    pub txid: Txid,
+    pub vout: u32,
+    pub amount: Amount,
+    pub status: PaymentStatus,
+    /// Zero while pending or removed.
+    pub confirmations: u32,
+}
+
+impl Payment {
+    pub fn outpoint(&self) -> OutPoint {
+        OutPoint::new(self.txid, self.vout)
+    }
+}
+
+enum Source {
+    /// The node's wallet tracks the watched descriptors.
+    Wallet { last_block: BlockHash },
+    /// Blocks and the mempool are matched against the watched scripts.
+    Blocks {
+        scripts: HashSet<ScriptBuf>,
+        follower: ChainFollower,
+        mempool: HashSet<Txid>,
+    },
+}
+
+/// Watches addresses and descriptors for incoming payments.
+///
+/// A payment is reported when first seen, then every time its status or
+/// confirmation count changes, until it reaches the configured number of
+/// confirmations or is removed. A reorg sends confirmed payments back to
+/// [PaymentStatus::Pending].
+///
+/// Two sources are supported:
+/// - [PaymentWatcher::wallet] imports the watched descriptors into the
+///   loaded wallet and follows it with `listsinceblock`. The wallet must have
+///   private keys disabled to import descriptors without keys.
+/// - [PaymentWatcher::blocks] needs no wallet and matches the outputs of
+///   every new block and mempool transaction itself.
+pub struct PaymentWatcher {
+    client: Arc<Client>,
+    source: Source,
+    confirmations: u32,
+    payments: HashMap<OutPoint, Payment>,
+}
+
+impl PaymentWatcher {
+    /// Creates a watcher backed by the wallet, reporting payments from the
+    /// current tip onwards.
+    pub async fn wallet(client: Arc<Client>) -> Result<Self> {
+        let last_block = client.get_best_block_hash().await?;
+        Ok(Self::new(client, Source::Wallet { last_block }))
+    }
+
+    /// Creates a wallet-less watcher following blocks from the current tip,
+    /// and transactions entering the mempool from now on.
+    pub async fn blocks(client: Arc<Client>) -> Result<Self> {
+        let follower = ChainFollower::from_tip(&client).await?;
+        let mempool = client.get_raw_mempool().await?.into_iter().collect();
+        Ok(Self::new(
+            client,
+            Source::Blocks {
+                scripts: HashSet::new(),
+                follower,
+                mempool,
+            },
+        ))
+    }
+
+    fn new(client: Arc<Client>, source: Source) -> Self {
+        Self {
+            client,
+            source,
+            confirmations: DEFAULT_CONFIRMATIONS,
+            payments: HashMap::new(),
+        }
+    }
+
+    /// Sets the number of confirmations after which a payment is no longer
+    /// reported.
+    pub fn confirmations(mut self, confirmations: u32) -> Self {
+        self.confirmations = confirmations.max(1);
+        self
+    }
+
+    /// Watches payments to `address`.
+    pub async fn watch_address(&mut self, address: &Address<NetworkUnchecked>) -> Result<()> {
+        let address = address.clone().assume_checked();
+        match &mut self.source {
+            Source::Wallet { .. } => {
+                self.import(&format!("addr({address})"), None, Some(address.to_string()))
+                    .await
+            }
+            Source::Blocks { scripts, .. } => {
+                scripts.insert(address.script_pubkey());
+                Ok(())
+            }
+        }
+    }
+
+    /// Watches payments to the addresses of `descriptor`. Ranged descriptors
+    /// need a `range`.
+    pub async fn watch_descriptor(
+        &mut self,
+        descriptor: &str,
+        range: Option<[u32; 2]>,
+    ) -> Result<()> {
+        match &mut self.source {
+            Source::Wallet { .. } => self.import(descriptor, range, None).await,
+            Source::Blocks { scripts, .. } => {
+                let addresses = self.client.derive_addresses(descriptor, range).await?;
+                scripts.extend(
+                    addresses
+                        .into_iter()
+                        .map(|a| a.assume_checked().script_pubkey()),
+                );
+                Ok(())
+            }
+        }
+    }
+
+    async fn import(
+        &self,
+        descriptor: &str,
+        range: Option<[u32; 2]>,
+        label: Option<String>,
+    ) -> Result<()> {
+        let info = self.client.get_descriptor_info(descriptor).await?;
+        let req = ImportDescriptors {
+            descriptor: info.descriptor,
+            timestamp: Timestamp::Now,
+            range: range.map(|[start, end]| (start as usize, end as usize)),
+            label,
+            ..Default::default()
+        };
+        for res in self.client.import_descriptors(req).await? {
+            if let Some(err) = res.error.filter(|_| !res.success) {
+                return Err(Error::JsonRpcError(JsonRpcError {
+                    code: err.code,
+                    message: err.message,
+                    data: None,
+                }));
+            }
+        }
+        Ok(())
+    }
+
+    /// Payments not yet settled or removed.
+    pub fn pending(&self) -> impl Iterator<Item = &Payment> {
+        self.payments.values()
+    }
+
+    /// Catches up with the node and returns the payments that changed since
+    /// the last poll.
+    pub async fn poll(&mut self) -> Result<Vec<Payment>> {
+        let updates = match &mut self.source {
+            Source::Wallet { last_block } => {
+                Self::poll_wallet(&self.client, last_block, self.confirmations).await?
+            }
+            Source::Blocks {
+                scripts,
+                follower,
+                mempool,
+            } => {
+                Self::poll_blocks(&self.client, scripts, follower, mempool, &self.payments).await?
+            }
+        };
+
+        let mut changed = Vec::new();
+        for payment in updates {
+            if let Some(payment) = self.record(payment) {
+                changed.push(payment);
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Stores the latest view of a payment, returning it if it should be
+    /// reported.
+    fn record(&mut self, payment: Payment) -> Option<Payment> {
+        let outpoint = payment.outpoint();
+        let previous = self.payments.get(&outpoint);
+        if previous.is_none() && payment.status == PaymentStatus::Removed {
+            return None;
+        }
+        if previous == Some(&payment) {
+            return None;
+        }
+        if payment.status == PaymentStatus::Removed || payment.confirmations >= self.confirmations {
+            self.payments.remove(&outpoint);
+        } else {
+            self.payments.insert(outpoint, payment.clone());
+        }
+        Some(payment)
+    }
+
+    async fn poll_wallet(
+        client: &Client,
+        last_block: &mut BlockHash,
+        confirmations: u32,
+    ) -> Result<Vec<Payment>> {
+        // Asking for `confirmations` makes `lastblock` lag behind the tip so
+        // that payments keep being listed until they are settled.
+        let res = client
+            .list_since_block(
+                Some(last_block),
+                Some(confirmations as usize),
+                Some(true),
+                Some(true),
+            )
+            .await?;
+        *last_block = res.lastblock;
+
+        let mut updates = Vec::new();
+        for entry in &res.removed {
+            if let Some(mut payment) = wallet_payment(entry) {
+                payment.status = PaymentStatus::Pending;
+                payment.confirmations = 0;
+                updates.push(payment);
+            }
+        }
+        updates.extend(res.transactions.iter().filter_map(wallet_payment));
+        Ok(updates)
+    }
+
+    async fn poll_blocks(
+        client: &Client,
+        scripts: &HashSet<ScriptBuf>,
+        follower: &mut ChainFollower,
+        mempool: &mut HashSet<Txid>,
+        payments: &HashMap<OutPoint, Payment>,
+    ) -> Result<Vec<Payment>> {
+        // Take the mempool before the chain, so that a transaction leaving
+        // the mempool in between is found in the new blocks.
+        let mut current: HashSet<Txid> = client.get_raw_mempool().await?.into_iter().collect();
+
+        let events = follower.poll(client).await?;
+        // A reorg returns the transactions of the disconnected blocks to the
+        // mempool, possibly after it was taken.
+        if events
+            .iter()
+            .any(|e| matches!(e, ChainEvent::Disconnected { .. }))
+        {
+            current.extend(client.get_raw_mempool().await?);
+        }
+        let mut blocks = HashMap::new();
+        let mut new_txs = Vec::new();
+        if !scripts.is_empty() {
+            let hashes: Vec<BlockHash> = events
+                .iter()
+                .filter_map(|e| match e {
+                    ChainEvent::Connected { hash, .. } => Some(*hash),
+                    ChainEvent::Disconnected { .. } => None,
+                })
+                .collect();
+            let fetched = client.get_blocks(&hashes).await?;
+            blocks.extend(hashes.into_iter().zip(fetched));
+
+            let args: Vec<[Value; 2]> = current
+                .difference(mempool)
+                .map(|txid| [txid.to_string().into(), false.into()])
+                .collect();
+            let calls: Vec<(&str, &[Value])> = args
+                .iter()
+                .map(|a| ("getrawtransaction", a.as_slice()))
+                .collect();
+            let results: Vec<Result<String>> = client.call_batch(&calls).await?;
+            // A transaction may have been mined or evicted meanwhile.
+            for hex in results.into_iter().flatten() {
+                let tx = encode::deserialize_hex(&hex).map_err(bitcoincore_rpc::Error::from)?;
+                new_txs.push(tx);
+            }
+        }
+        *mempool = current;
+
+        let (tip_height, _) = follower.tip();
+        Ok(block_updates(
+            scripts, &events, &blocks, &new_txs, mempool, payments, tip_height,
+        ))
+    }
+
+    /// Polls every `poll_interval` on a background task and yields the
+    /// payments as a stream.
+    ///
+    /// The stream ends after the first error. Must be called from within a
+    /// Tokio runtime.
+    pub fn into_stream(mut self, poll_interval: Duration) -> PaymentStream {
+        let (tx, rx) = mpsc::channel(64);
+        let task = tokio::spawn(async move {
+            let mut ticker = interval(poll_interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                match self.poll().await {
+                    Ok(payments) => {
+                        for payment in payments {
+                            if tx.send(Ok(payment)).await.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                    Err(err) => {
+                        let _ = tx.send(Err(err)).await;
+                        return;
+                    }
+                }
+            }
+        });
+        PaymentStream { rx, task }
+    }
+}
+
+/// Applies the chain `events` and the transactions `new_txs`, new in the
+/// mempool, to `payments`, and returns their latest view.
+///
+/// `blocks` holds the connected blocks, and `mempool` the txids in the
+/// mempool, taken before the chain events and again after a reorg.
+fn block_updates(
+    scripts: &HashSet<ScriptBuf>,
+    events: &[ChainEvent],
+    blocks: &HashMap<BlockHash, Block>,
+    new_txs: &[Transaction],
+    mempool: &HashSet<Txid>,
+    payments: &HashMap<OutPoint, Payment>,
+    tip_height: u64,
+) -> Vec<Payment> {
+    let mut updates: HashMap<OutPoint, Payment> = HashMap::new();
+    for event in events {
+        match *event {
+            ChainEvent::Disconnected { hash, .. } => {
+                let reorged = payments.values().chain(updates.values()).filter(|p| {
+                    matches!(p.status, PaymentStatus::Confirmed { block_hash, .. } if block_hash == hash)
+                });
+                let reorged: Vec<_> = reorged
+                    .map(|p| Payment {
+                        status: PaymentStatus::Pending,
+                        confirmations: 0,
+                        ..p.clone()
+                    })
+                    .collect();
+                for payment in reorged {
+                    updates.insert(payment.outpoint(), payment);
+                }
+            }
+            ChainEvent::Connected { height, hash } => {
+                let Some(block) = blocks.get(&hash) else {
+                    continue;
+                };
+                let status = PaymentStatus::Confirmed {
+                    block_hash: hash,
+                    height,
+                };
+                for tx in &block.txdata {
+                    for payment in tx_payments(scripts, tx, status) {
+                        updates.insert(payment.outpoint(), payment);
+                    }
+                }
+            }
+        }
+    }
+
+    for tx in new_txs {
+        for payment in tx_payments(scripts, tx, PaymentStatus::Pending) {
+            updates.entry(payment.outpoint()).or_insert(payment);
+        }
+    }
+
+    // Pending payments that are neither in a block nor in the mempool were
+    // replaced or evicted.
+    for payment in payments.values() {
+        let outpoint = payment.outpoint();
+        let latest = updates.get(&outpoint).unwrap_or(payment);
+        if latest.status == PaymentStatus::Pending && !mempool.contains(&payment.txid) {
+            updates.insert(
+                outpoint,
+                Payment {
+                    status: PaymentStatus::Removed,
+                    ..payment.clone()
+                },
+            );
+        }
+    }
+
+    for payment in payments.values() {
+        updates
+            .entry(payment.outpoint())
+            .or_insert_with(|| payment.clone());
+    }
+    let mut updates: Vec<Payment> = updates.into_values().collect();
+    for payment in &mut updates {
+        if let PaymentStatus::Confirmed { height, .. } = payment.status {
+            payment.confirmations = (tip_height + 1).saturating_sub(height) as u32;
+        }
+    }
+    updates
+}
+
+/// The outputs of `tx` paying one of `scripts`, with `status`.
+fn tx_payments<'a>(
+    scripts: &'a HashSet<ScriptBuf>,
+    tx: &'a Transaction,
+    status: PaymentStatus,
+) -> impl Iterator<Item = Payment> + 'a {
+    let txid = tx.compute_txid();
+    tx.output
+        .iter()
+        .enumerate()
+        .filter(|(_, output)| scripts.contains(&output.script_pubkey))
+        .map(move |(vout, output)| Payment {
+            txid,
+            vout: vout as u32,
+            amount: output.value,
+            status,
+            confirmations: 0,
+        })
+}
+
+/// Turns a `listsinceblock` entry into a payment, if it is a receive.
+fn wallet_payment(entry: &ListTransactionResult) -> Option<Payment> {
+    if !matches!(
+        entry.detail.category,
+        GetTransactionResultDetailCategory::Receive
+            | GetTransactionResultDetailCategory::Generate
+            | GetTransactionResultDetailCategory::Immature
+    ) {
+        return None;
+    }
+    let info = &entry.info;
+    let (status, confirmations) = match (info.confirmations, info.blockhash, info.blockheight) {
+        (c, Some(block_hash), Some(height)) if c > 0 => (
+            PaymentStatus::Confirmed {
+                block_hash,
+                height: height as u64,
+            },
+            c as u32,
+        ),
+        (c, ..) if c < 0 || entry.detail.abandoned == Some(true) => (PaymentStatus::Removed, 0),
+        _ => (PaymentStatus::Pending, 0),
+    };
+    Some(Payment {
+        txid: info.txid,
+        vout: entry.detail.vout,
+        amount: entry.detail.amount.unsigned_abs(),
+        status,
+        confirmations,
+    })
+}
+
+/// Payments reported by a [PaymentWatcher] polling in the background.
+///
+/// Dropping the stream stops the watcher.
+pub struct PaymentStream {
+    rx: mpsc::Receiver<Result<Payment>>,
+    task: JoinHandle<()>,
+}
+
+impl PaymentStream {
+    /// Waits for the next payment update.
+    pub async fn next(&mut self) -> Option<Result<Payment>> {
+        self.rx.recv().await
+    }
+}
+
+impl Stream for PaymentStream {
+    type Item = Result<Payment>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+impl Drop for PaymentStream {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc_json::bitcoin::{
+        absolute::LockTime, block, hashes::Hash, transaction, CompactTarget, TxMerkleNode, TxOut,
+    };
+
+    fn watcher() -> PaymentWatcher {
+        let client = Client::new("http://127.0.0.1:8332", bitcoincore_rpc::Auth::None).unwrap();
+        PaymentWatcher::new(
+            Arc::new(client),
+            Source::Wallet {
+                last_block: BlockHash::all_zeros(),
+            },
+        )
+        .confirmations(2)
+    }
+
+    fn payment(status: PaymentStatus, confirmations: u32) -> Payment {
+        Payment {
+            txid: Txid::all_zeros(),
+            vout: 1,
+            amount: Amount::from_sat(10_000),
+            status,
+            confirmations,
+        }
+    }
+
+    #[test]
+    fn reports_changes_until_settled() {
+        let mut watcher = watcher();
+        let block_hash = BlockHash::all_zeros();
+        let confirmed = PaymentStatus::Confirmed {
+            block_hash,
+            height: 10,
+        };
+
+        assert!(watcher.record(payment(PaymentStatus::Pending, 0)).is_some());
+        assert!(watcher.record(payment(PaymentStatus::Pending, 0)).is_none());
+        assert!(watcher.record(payment(confirmed, 1)).is_some());
+        // Reorged out, back to pending.
+        assert!(watcher.record(payment(PaymentStatus::Pending, 0)).is_some());
+        assert!(watcher.record(payment(confirmed, 1)).is_some());
+        assert!(watcher.record(payment(confirmed, 2)).is_some());
+        assert_eq!(watcher.pending().count(), 0);
+    }
+
+    #[test]
+    fn ignores_removed_unknown_payments() {
+        let mut watcher = watcher();
+        assert!(watcher.record(payment(PaymentStatus::Removed, 0)).is_none());
+
+        assert!(watcher.record(payment(PaymentStatus::Pending, 0)).is_some());
+        assert!(watcher.record(payment(PaymentStatus::Removed, 0)).is_some());
+        assert_eq!(watcher.pending().count(), 0);
+    }
+
+    fn tx_paying(script: &ScriptBuf, lock_time: u32) -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: LockTime::from_consensus(lock_time),
+            input: vec![],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(1_000),
+                    script_pubkey: ScriptBuf::new(),
+                },
+                TxOut {
+                    value: Amount::from_sat(10_000),
+                    script_pubkey: script.clone(),
+                },
+            ],
+        }
+    }
+
+    fn block_with(time: u32, txdata: Vec<Transaction>) -> Block {
+        Block {
+            header: block::Header {
+                version: block::Version::TWO,
+                prev_blockhash: BlockHash::all_zeros(),
+                merkle_root: TxMerkleNode::all_zeros(),
+                time,
+                bits: CompactTarget::from_consensus(0x207fffff),
+                nonce: 0,
+            },
+            txdata,
+        }
+    }
+
+    #[test]
+    fn matches_blocks_and_mempool() {
+        let script = ScriptBuf::from_bytes(vec![0x51]);
+        let scripts = HashSet::from([script.clone()]);
+        let mined = tx_paying(&script, 1);
+        let block = block_with(1, vec![mined.clone()]);
+        let hash = block.block_hash();
+        let unconfirmed = tx_paying(&script, 2);
+
+        let updates = block_updates(
+            &scripts,
+            &[ChainEvent::Connected { height: 10, hash }],
+            &HashMap::from([(hash, block)]),
+            std::slice::from_ref(&unconfirmed),
+            &HashSet::from([unconfirmed.compute_txid()]),
+            &HashMap::new(),
+            11,
+        );
+        assert_eq!(updates.len(), 2);
+        let confirmed = updates
+            .iter()
+            .find(|p| p.txid == mined.compute_txid())
+            .unwrap();
+        assert_eq!(
+            confirmed.status,
+            PaymentStatus::Confirmed {
+                block_hash: hash,
+                height: 10
+            }
+        );
+        assert_eq!((confirmed.vout, confirmed.confirmations), (1, 2));
+        let pending = updates
+            .iter()
+            .find(|p| p.txid == unconfirmed.compute_txid())
+            .unwrap();
+        assert_eq!(pending.status, PaymentStatus::Pending);
+        assert_eq!(pending.amount, Amount::from_sat(10_000));
+    }
+
+    #[test]
+    fn reorgs_back_to_pending_or_removed() {
+        let script = ScriptBuf::from_bytes(vec![0x51]);
+        let scripts = HashSet::from([script.clone()]);
+        let block = block_with(1, vec![tx_paying(&script, 1), tx_paying(&script, 2)]);
+        let hash = block.block_hash();
+        let confirmed = PaymentStatus::Confirmed {
+            block_hash: hash,
+            height: 10,
+        };
+        let payments: HashMap<_, _> = block
+            .txdata
+            .iter()
+            .flat_map(|tx| tx_payments(&scripts, tx, confirmed))
+            .map(|p| (p.outpoint(), p))
+            .collect();
+        let back = block.txdata[0].compute_txid();
+
+        // Only the transaction back in the mempool is still pending.
+        let updates = block_updates(
+            &scripts,
+            &[ChainEvent::Disconnected { height: 10, hash }],
+            &HashMap::new(),
+            &[],
+            &HashSet::from([back]),
+            &payments,
+            9,
+        );
+        for payment in updates {
+            let status = if payment.txid == back {
+                PaymentStatus::Pending
+            } else {
+                PaymentStatus::Removed
+            };
+            assert_eq!((payment.status, payment.confirmations), (status, 0));
+        }
+
+        // Reconnected in another block.
+        let other = block_with(2, block.txdata.clone());
+        let other_hash = other.block_hash();
+        let updates = block_updates(
+            &scripts,
+            &[
+                ChainEvent::Disconnected { height: 10, hash },
+                ChainEvent::Connected {
+                    height: 10,
+                    hash: other_hash,
+                },
+            ],
+            &HashMap::from([(other_hash, other)]),
+            &[],
+            &HashSet::new(),
+            &payments,
+            10,
+        );
+        assert_eq!(updates.len(), 2);
+        assert!(updates.iter().all(|p| p.status
+            == PaymentStatus::Confirmed {
+                block_hash: other_hash,
+                height: 10
+            }
+            && p.confirmations == 1));
+    }
+
+    #[test]
+    fn removes_evicted_payments() {
+        let script = ScriptBuf::from_bytes(vec![0x51]);
+        let scripts = HashSet::from([script.clone()]);
+        let kept = tx_payments(&scripts, &tx_paying(&script, 1), PaymentStatus::Pending)
+            .next()
+            .unwrap();
+        let evicted = tx_payments(&scripts, &tx_paying(&script, 2), PaymentStatus::Pending)
+            .next()
+            .unwrap();
+        let payments = HashMap::from([
+            (kept.outpoint(), kept.clone()),
+            (evicted.outpoint(), evicted.clone()),
+        ]);
+
+        let updates = block_updates(
+            &scripts,
+            &[],
+            &HashMap::new(),
+            &[],
+            &HashSet::from([kept.txid]),
+            &payments,
+            10,
+        );
+        assert_eq!(updates.len(), 2);
+        assert!(updates.contains(&kept));
+        assert!(updates.contains(&Payment {
+            status: PaymentStatus::Removed,
+            ..evicted
+        }));
+    }
+}
@@ -0,0 +1,138 @@
+use std::{convert::Infallible, future::Future, time::Duration};
+
+use tokio::{
+    sync::watch,
+    task::JoinHandle,
+    time::{interval, MissedTickBehavior},
+};
+
+use crate::client::Result;
+
+/// Calls `poll` every `poll_interval`, starting right away, until `done`
+/// returns true for its result, and returns that result.
+///
+/// Fails with the first error returned by `poll`.
+pub(crate) async fn poll_until<T, Fut>(
+    poll_interval: Duration,
+    mut poll: impl FnMut() -> Fut,
+    mut done: impl FnMut(&T) -> bool,
+) -> Result<T>
+where
+    Fut: Future<Output = Result<T>>,
+{
+    let mut ticker = interval(poll_interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        let res = poll().await?;
+        if done(&res) {
+            return Ok(res);
+        }
+    }
+}
+
+/// Sends the progress returned by `poll` to `tx` every `poll_interval`,
+/// forever.
+async fn report_progress<Fut>(
+    poll_interval: Duration,
+    mut poll: impl FnMut() -> Fut,
+    tx: &watch::Sender<f64>,
+) -> Infallible
+where
+    Fut: Future<Output = Option<f64>>,
+{
+    let mut ticker = interval(poll_interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        if let Some(progress) = poll().await {
+            tx.send_replace(progress.clamp(0.0, 1.0));
+        }
+    }
+}
+
+/// A long-running call awaited on its own task, while its progress is
+/// polled into a watch channel, from 0 to 1.
+pub(crate) struct ProgressTask<T> {
+    progress: watch::Receiver<f64>,
+    task: JoinHandle<Result<T>>,
+}
+
+impl<T: Send + 'static> ProgressTask<T> {
+    /// Spawns `call`, polling its progress with `poll` every
+    /// `poll_interval`. `poll` returns `None` when the progress is unknown,
+    /// e.g. before the call registered on the node, and the last known
+    /// progress is kept then.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub(crate) fn spawn<Fut>(
+        call: impl Future<Output = Result<T>> + Send + 'static,
+        poll_interval: Duration,
+        poll: impl FnMut() -> Fut + Send + 'static,
+    ) -> Self
+    where
+        Fut: Future<Output = Option<f64>> + Send,
+    {
+        let (tx, progress) = watch::channel(0.0);
+        let task = tokio::spawn(async move {
+            tokio::select! {
+                res = call => {
+                    if res.is_ok() {
+                        tx.send_replace(1.0);
+                    }
+                    res
+                }
+                never = report_progress(poll_interval, poll, &tx) => match never {},
+            }
+        });
+        Self { progress, task }
+    }
+
+    pub(crate) fn progress(&self) -> watch::Receiver<f64> {
+        self.progress.clone()
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+
+    pub(crate) async fn wait(self) -> Result<T> {
+        self.task.await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    };
+
+    #[tokio::test]
+    async fn polls_progress_until_the_call_completes() {
+        let polls = Arc::new(AtomicU32::new(0));
+        let task = ProgressTask::spawn(
+            async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Ok(7)
+            },
+            Duration::from_millis(1),
+            {
+                let polls = polls.clone();
+                move || {
+                    let n = polls.fetch_add(1, Ordering::SeqCst);
+                    async move { (n > 0).then_some(0.5) }
+                }
+            },
+        );
+        let mut progress = task.progress();
+        progress.wait_for(|p| *p == 0.5).await.unwrap();
+        assert_eq!(task.wait().await.unwrap(), 7);
+        assert_eq!(*progress.borrow(), 1.0);
+        assert!(polls.load(Ordering::SeqCst) > 1);
+
+        let status = poll_until(Duration::from_millis(1), || async { Ok(3) }, |n| *n == 3);
+        assert_eq!(status.await.unwrap(), 3);
+    }
+}
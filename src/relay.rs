@@ -1,6 +1,6 @@
 use crate::{
     error::Error,
-    jsonrpc::{Request, Response},
+    jsonrpc::{ErrorResponse, Request, Response},
 };
 
 use reqwest::Client;
@@ -36,23 +36,78 @@ impl Relay {
         method: &str,
         params: T,
     ) -> Result<R, Error> {
-        let next_id = self.id.load(Ordering::SeqCst) + 1;
-        self.id.store(next_id, Ordering::SeqCst);
+        let next_id = self.id.fetch_add(1, Ordering::SeqCst) + 1;
 
         let payload = Request::new(next_id, method, params);
+        let res: Response<R> = self.post(&payload).await?;
 
+        Ok(res.data.into_result()?)
+    }
+
+    /// Sends several requests in a single JSON-RPC batch.
+    ///
+    /// Results are returned in the order of `requests`, each one failing on
+    /// its own if the server returned an error for it.
+    pub async fn batch_request<T: Serialize + Send + Sync, R: DeserializeOwned>(
+        &self,
+        requests: &[(&str, T)],
+    ) -> Result<Vec<Result<R, Error>>, Error> {
+        if requests.is_empty() {
+            return Ok(Vec::new());
+        }
+        let first_id = self.id.fetch_add(requests.len() as u64, Ordering::SeqCst) + 1;
+
+        let payload: Vec<_> = (first_id..)
+            .zip(requests)
+            .map(|(id, (method, params))| Request::new(id, method, params))
+            .collect();
+        let responses: Vec<Response<serde_json::Value>> = self.post(&payload).await?;
+
+        // The server may answer a batch in any order.
+        let mut results: Vec<Option<Result<R, Error>>> = requests.iter().map(|_| None).collect();
+        for response in responses {
+            let idx = response.id.wrapping_sub(first_id) as usize;
+            if let Some(slot) = results.get_mut(idx) {
+                *slot = Some(
+                    response
+                        .data
+                        .into_result()
+                        .map_err(Error::from)
+                        .and_then(|v| serde_json::from_value(v).map_err(Error::from)),
+                );
+            }
+        }
+        results
+            .into_iter()
+            .map(|r| {
+                r.ok_or_else(|| Error::ServerError {
+                    text: "missing response in batch".into(),
+                })
+            })
+            .collect()
+    }
+
+    async fn post<P: Serialize + Send + Sync, R: DeserializeOwned>(
+        &self,
+        payload: &P,
+    ) -> Result<R, Error> {
         let mut req = self.client.post(self.url.as_ref());
 
         if let (Some(user), Some(pass)) = (&self.user, &self.pass) {
             req = req.basic_auth(user, Some(pass));
         }
 
-        let res = req.json(&payload).send().await?;
+        let res = req.json(payload).send().await?;
         let status = res.error_for_status_ref();
 
         match status {
             Err(err) => {
                 let text = res.text().await?;
+                // Before v28, and for JSON-RPC 1.0 requests, Core reports RPC
+                // errors with an HTTP error status.
+                if let Ok(ErrorResponse { error }) = serde_json::from_str(&text) {
+                    return Err(error.into());
+                }
                 let status_code = err.status().unwrap();
                 if status_code.is_client_error() {
                     // Client error (400-499)
@@ -64,10 +119,7 @@ impl Relay {
             }
             Ok(_) => {
                 let text = res.text().await?;
-                let res: Response<R> = serde_json::from_str(&text)
-                    .map_err(|err| Error::ResponseSerdeJson { err, text })?;
-
-                Ok(res.data.into_result()?)
+                serde_json::from_str(&text).map_err(|err| Error::ResponseSerdeJson { err, text })
             }
         }
     }
@@ -84,3 +136,57 @@ impl Clone for Relay {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    /// Serves a single request with `status` and `body`, like Core does.
+    fn serve_once(status: &'static str, body: &'static str) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"}") {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            let response = format!(
+                "HTTP/1.1 {status}\r\nContent-Type: application/json\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn parses_rpc_errors_sent_with_http_errors() {
+        let url = serve_once(
+            "500 Internal Server Error",
+            r#"{"result":null,"error":{"code":-27,"message":"Transaction already in block chain"},"id":1}"#,
+        );
+        let relay = Relay::new(url, None, None);
+        let err = relay
+            .request::<_, String>("sendrawtransaction", ["00"])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::JsonRpcError(e) if e.code == -27));
+
+        let url = serve_once("503 Service Unavailable", "Loading block index...");
+        let relay = Relay::new(url, None, None);
+        let err = relay
+            .request::<_, String>("getblockcount", ())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::ServerError { text } if text.starts_with("Loading")));
+    }
+}
@@ -0,0 +1,73 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::sync::watch;
+
+use crate::{
+    client::{Client, Result},
+    progress::ProgressTask,
+};
+
+/// A wallet `rescanblockchain` running in the background.
+///
+/// The rescan is started on its own task while the progress reported by
+/// `getwalletinfo` is polled into a watch channel.
+pub struct RescanHandle {
+    client: Arc<Client>,
+    task: ProgressTask<(usize, Option<usize>)>,
+}
+
+impl RescanHandle {
+    /// Starts rescanning blocks `start_height..=stop_height`, or up to the
+    /// tip, polling progress every `poll_interval`.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn start(
+        client: Arc<Client>,
+        start_height: Option<usize>,
+        stop_height: Option<usize>,
+        poll_interval: Duration,
+    ) -> Self {
+        let rescan = {
+            let client = client.clone();
+            async move { client.rescan_blockchain(start_height, stop_height).await }
+        };
+        let poll = {
+            let client = client.clone();
+            move || {
+                let client = client.clone();
+                async move {
+                    let status = client.get_rescan_progress().await.ok()??;
+                    Some(status.progress)
+                }
+            }
+        };
+        Self {
+            client,
+            task: ProgressTask::spawn(rescan, poll_interval, poll),
+        }
+    }
+
+    /// Subscribes to the rescan progress, from 0 to 1.
+    pub fn progress(&self) -> watch::Receiver<f64> {
+        self.task.progress()
+    }
+
+    /// Returns true once the rescan has completed, failed or been aborted.
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+
+    /// Asks the node to abort the rescan.
+    ///
+    /// Returns `false` if the node had no rescan to abort. The node then
+    /// fails the rescan, which is reported by [Self::wait].
+    pub async fn abort(&self) -> Result<bool> {
+        self.client.abort_rescan().await
+    }
+
+    /// Waits for the rescan to finish and returns the heights it started and
+    /// stopped at.
+    pub async fn wait(self) -> Result<(usize, Option<usize>)> {
+        self.task.wait().await
+    }
+}
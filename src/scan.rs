@@ -0,0 +1,73 @@
+use std::{sync::Arc, time::Duration};
+
+use bitcoincore_rpc_json::{ScanTxOutRequest, ScanTxOutResult};
+use tokio::sync::watch;
+
+use crate::{
+    client::{Client, Result},
+    progress::ProgressTask,
+};
+
+/// A `scantxoutset` scan running in the background.
+///
+/// The scan is started on its own task while the progress reported by
+/// `scantxoutset status` is polled into a watch channel.
+pub struct ScanHandle {
+    client: Arc<Client>,
+    task: ProgressTask<ScanTxOutResult>,
+}
+
+impl ScanHandle {
+    /// Starts scanning the UTXO set for `descriptors`, polling progress every
+    /// `poll_interval`.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn start(
+        client: Arc<Client>,
+        descriptors: Vec<ScanTxOutRequest>,
+        poll_interval: Duration,
+    ) -> Self {
+        let scan = {
+            let client = client.clone();
+            async move { client.scan_tx_out_set_blocking(&descriptors).await }
+        };
+        let poll = {
+            let client = client.clone();
+            move || {
+                let client = client.clone();
+                async move {
+                    // The node reports percents.
+                    let status = client.scan_tx_out_set_status().await.ok()??;
+                    Some(status.progress / 100.0)
+                }
+            }
+        };
+        Self {
+            client,
+            task: ProgressTask::spawn(scan, poll_interval, poll),
+        }
+    }
+
+    /// Subscribes to the scan progress, from 0 to 1.
+    pub fn progress(&self) -> watch::Receiver<f64> {
+        self.task.progress()
+    }
+
+    /// Returns true once the scan has completed, failed or been aborted.
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+
+    /// Asks the node to abort the scan.
+    ///
+    /// Returns `false` if the node had no scan to abort. The outcome of the
+    /// scan is still reported by [Self::wait].
+    pub async fn abort(&self) -> Result<bool> {
+        self.client.scan_tx_out_set_abort().await
+    }
+
+    /// Waits for the scan to finish and returns its result.
+    pub async fn wait(self) -> Result<ScanTxOutResult> {
+        self.task.wait().await
+    }
+}
@@ -0,0 +1,230 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use bitcoincore_rpc_json::bitcoin::BlockHash;
+
+use crate::{
+    client::{Client, Result},
+    error::Error,
+    progress::poll_until,
+    types,
+};
+
+/// Where the node stands with a loaded UTXO snapshot.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SnapshotStatus {
+    /// No snapshot chainstate is in use.
+    None,
+    /// The background chainstate is validating the blocks up to the snapshot
+    /// base.
+    Validating {
+        base_hash: BlockHash,
+        base_height: u64,
+        /// Height the background chainstate has reached.
+        height: u64,
+    },
+    /// The background chainstate reached the snapshot base and found the
+    /// same UTXO set.
+    Validated { base_hash: BlockHash },
+    /// The snapshot is not validated, yet no background chainstate is
+    /// validating it, e.g. after background validation failed.
+    NotValidating { base_hash: BlockHash },
+}
+
+impl SnapshotStatus {
+    /// Returns the fraction of the blocks up to the snapshot base validated
+    /// so far, from 0 to 1, or `None` without a snapshot being validated.
+    pub fn progress(&self) -> Option<f64> {
+        match *self {
+            SnapshotStatus::None | SnapshotStatus::NotValidating { .. } => None,
+            SnapshotStatus::Validating {
+                base_height,
+                height,
+                ..
+            } => Some(height.min(base_height) as f64 / base_height.max(1) as f64),
+            SnapshotStatus::Validated { .. } => Some(1.0),
+        }
+    }
+}
+
+/// Maps the chainstates of `getchainstates` to the snapshot status, looking
+/// up the height of the snapshot base with `base_height` while validating.
+async fn status_from<Fut>(
+    states: &types::GetChainStatesResult,
+    base_height: impl FnOnce(BlockHash) -> Fut,
+) -> Result<SnapshotStatus>
+where
+    Fut: Future<Output = Result<u64>>,
+{
+    let Some((snapshot, base_hash)) = states
+        .chainstates
+        .iter()
+        .find_map(|c| Some((c, c.snapshot_blockhash?)))
+    else {
+        return Ok(SnapshotStatus::None);
+    };
+    if snapshot.validated {
+        return Ok(SnapshotStatus::Validated { base_hash });
+    }
+    let background = states
+        .chainstates
+        .iter()
+        .find(|c| c.snapshot_blockhash.is_none());
+    match background {
+        Some(background) => Ok(SnapshotStatus::Validating {
+            base_hash,
+            base_height: base_height(base_hash).await?,
+            height: background.blocks,
+        }),
+        None => Ok(SnapshotStatus::NotValidating { base_hash }),
+    }
+}
+
+/// Dumps and loads assumeutxo snapshots, and follows the background
+/// validation of a loaded one.
+pub struct SnapshotManager {
+    client: Arc<Client>,
+}
+
+impl SnapshotManager {
+    pub fn new(client: Arc<Client>) -> Self {
+        Self { client }
+    }
+
+    /// Writes a snapshot of the UTXO set at the tip, or at height
+    /// `rollback`, to `path`.
+    pub async fn dump(
+        &self,
+        path: &str,
+        rollback: Option<u64>,
+    ) -> Result<types::DumpTxOutSetResult> {
+        self.client.dump_tx_out_set(path, rollback).await
+    }
+
+    /// Loads the snapshot at `path`, whose validation can then be followed
+    /// with [Self::status] or [Self::wait_validated].
+    pub async fn load(&self, path: &str) -> Result<types::LoadTxOutSetResult> {
+        self.client.load_tx_out_set(path).await
+    }
+
+    /// Returns the status of the snapshot chainstate, from `getchainstates`.
+    pub async fn status(&self) -> Result<SnapshotStatus> {
+        let states = self.client.get_chain_states().await?;
+        status_from(&states, |base_hash| async move {
+            let info = self.client.get_block_header_info(&base_hash).await?;
+            Ok(info.height as u64)
+        })
+        .await
+    }
+
+    /// Polls [Self::status] every `poll_interval` until the snapshot is
+    /// validated, passing each status to `on_progress`.
+    ///
+    /// Returns the snapshot base block, or `None` if no snapshot is loaded.
+    /// Fails with [Error::SnapshotNotValidating] if the snapshot stops being
+    /// validated.
+    pub async fn wait_validated(
+        &self,
+        poll_interval: Duration,
+        mut on_progress: impl FnMut(&SnapshotStatus),
+    ) -> Result<Option<BlockHash>> {
+        let status = poll_until(
+            poll_interval,
+            || self.status(),
+            |status| {
+                on_progress(status);
+                !matches!(status, SnapshotStatus::Validating { .. })
+            },
+        )
+        .await?;
+        match status {
+            SnapshotStatus::None => Ok(None),
+            SnapshotStatus::Validated { base_hash } => Ok(Some(base_hash)),
+            SnapshotStatus::NotValidating { base_hash }
+            | SnapshotStatus::Validating { base_hash, .. } => {
+                Err(Error::SnapshotNotValidating { base_hash })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc_json::bitcoin::hashes::Hash;
+    use serde_json::{json, Value};
+
+    const BASE: &str = "0000000000000000000320283a032748cef8227873ff4872689bf23f1cda83a5";
+
+    fn chainstate(blocks: u64, snapshot: bool, validated: bool) -> Value {
+        let mut state = json!({
+            "blocks": blocks,
+            "bestblockhash": "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206",
+            "difficulty": 1.0,
+            "verificationprogress": 1.0,
+            "coins_db_cache_bytes": 8388608,
+            "coins_tip_cache_bytes": 471859200,
+            "validated": validated,
+        });
+        if snapshot {
+            state["snapshot_blockhash"] = BASE.into();
+        }
+        state
+    }
+
+    async fn status_of(chainstates: Vec<Value>) -> SnapshotStatus {
+        let states: types::GetChainStatesResult =
+            serde_json::from_value(json!({ "headers": 840_100, "chainstates": chainstates }))
+                .unwrap();
+        status_from(&states, |_| async { Ok(840_000) })
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn maps_chainstates_to_status() {
+        let base_hash: BlockHash = BASE.parse().unwrap();
+
+        let status = status_of(vec![chainstate(840_100, false, true)]).await;
+        assert_eq!(status, SnapshotStatus::None);
+        assert_eq!(status.progress(), None);
+
+        let status = status_of(vec![
+            chainstate(210_000, false, true),
+            chainstate(840_100, true, false),
+        ])
+        .await;
+        assert_eq!(
+            status,
+            SnapshotStatus::Validating {
+                base_hash,
+                base_height: 840_000,
+                height: 210_000,
+            }
+        );
+        assert_eq!(status.progress(), Some(0.25));
+
+        let status = status_of(vec![chainstate(840_100, true, true)]).await;
+        assert_eq!(status, SnapshotStatus::Validated { base_hash });
+        assert_eq!(status.progress(), Some(1.0));
+
+        let status = status_of(vec![chainstate(840_100, true, false)]).await;
+        assert_eq!(status, SnapshotStatus::NotValidating { base_hash });
+        assert_eq!(status.progress(), None);
+    }
+
+    #[test]
+    fn caps_progress_at_the_base() {
+        let status = SnapshotStatus::Validating {
+            base_hash: BlockHash::all_zeros(),
+            base_height: 100,
+            height: 150,
+        };
+        assert_eq!(status.progress(), Some(1.0));
+        let status = SnapshotStatus::Validating {
+            base_hash: BlockHash::all_zeros(),
+            base_height: 0,
+            height: 0,
+        };
+        assert_eq!(status.progress(), Some(0.0));
+    }
+}
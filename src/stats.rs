@@ -0,0 +1,250 @@
+use std::{collections::BTreeMap, fmt::Write as _, ops::RangeInclusive, time::Duration};
+
+use bitcoincore_rpc_json::{
+    bitcoin::{amount::serde::as_sat, Amount},
+    GetBlockStatsResult,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    client::{Client, Result},
+    types::HashOrHeight,
+};
+
+/// Aggregated statistics of the blocks whose time falls in a bucket.
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct StatsBucket {
+    /// Start of the bucket, in seconds since the epoch.
+    pub start: u64,
+    pub blocks: u64,
+    pub first_height: u64,
+    pub last_height: u64,
+    /// Transactions, excluding coinbases.
+    pub txs: u64,
+    #[serde(with = "as_sat")]
+    pub total_fee: Amount,
+    /// Average fee rate in sat/vB, over the total weight of the
+    /// transactions.
+    pub avg_fee_rate: f64,
+    /// The 10th, 25th, 50th, 75th and 90th fee rate percentiles in sat/vB,
+    /// averaged over the blocks weighted by their transaction count.
+    pub fee_rate_percentiles: [f64; 5],
+    /// Fraction of the transactions spending segwit outputs.
+    pub segwit_share: f64,
+}
+
+/// Block statistics aggregated into buckets of equal duration.
+#[derive(Clone, PartialEq, Debug, Default, Deserialize, Serialize)]
+pub struct StatsSeries {
+    /// Duration of a bucket in seconds.
+    pub bucket_secs: u64,
+    /// The non-empty buckets, oldest first.
+    pub buckets: Vec<StatsBucket>,
+}
+
+impl StatsSeries {
+    /// Aggregates `stats` into buckets of `bucket` by block time.
+    pub fn aggregate(stats: &[GetBlockStatsResult], bucket: Duration) -> Self {
+        let bucket_secs = bucket.as_secs().max(1);
+        let mut by_bucket: BTreeMap<u64, Vec<&GetBlockStatsResult>> = BTreeMap::new();
+        for block in stats {
+            let start = block.time - block.time % bucket_secs;
+            by_bucket.entry(start).or_default().push(block);
+        }
+        let buckets = by_bucket
+            .into_iter()
+            .map(|(start, blocks)| {
+                // "txs" counts the coinbase, unlike the other statistics.
+                let txs = |b: &GetBlockStatsResult| b.txs.saturating_sub(1) as u64;
+                let total_txs: u64 = blocks.iter().map(|b| txs(b)).sum();
+                let total_fee: Amount = blocks.iter().map(|b| b.total_fee).sum();
+                let total_weight: u64 = blocks.iter().map(|b| b.total_weight as u64).sum();
+                let sw_txs: u64 = blocks.iter().map(|b| b.sw_txs as u64).sum();
+
+                let mut fee_rate_percentiles = [0.0; 5];
+                if total_txs > 0 {
+                    for block in &blocks {
+                        let p = &block.fee_rate_percentiles;
+                        let weight = txs(block) as f64 / total_txs as f64;
+                        for (acc, rate) in fee_rate_percentiles
+                            .iter_mut()
+                            .zip([p.fr_10th, p.fr_25th, p.fr_50th, p.fr_75th, p.fr_90th])
+                        {
+                            *acc += rate.to_sat() as f64 * weight;
+                        }
+                    }
+                }
+                StatsBucket {
+                    start,
+                    blocks: blocks.len() as u64,
+                    first_height: blocks.iter().map(|b| b.height).min().unwrap_or_default(),
+                    last_height: blocks.iter().map(|b| b.height).max().unwrap_or_default(),
+                    txs: total_txs,
+                    total_fee,
+                    avg_fee_rate: ratio(total_fee.to_sat() * 4, total_weight),
+                    fee_rate_percentiles,
+                    segwit_share: ratio(sw_txs, total_txs),
+                }
+            })
+            .collect();
+        Self {
+            bucket_secs,
+            buckets,
+        }
+    }
+
+    /// Formats the series as CSV, one bucket per line after a header line.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "start,blocks,first_height,last_height,txs,total_fee,avg_fee_rate,\
+             fee_rate_p10,fee_rate_p25,fee_rate_p50,fee_rate_p75,fee_rate_p90,segwit_share\n",
+        );
+        for b in &self.buckets {
+            let [p10, p25, p50, p75, p90] = b.fee_rate_percentiles;
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{:.4}",
+                b.start,
+                b.blocks,
+                b.first_height,
+                b.last_height,
+                b.txs,
+                b.total_fee.to_sat(),
+                b.avg_fee_rate,
+                p10,
+                p25,
+                p50,
+                p75,
+                p90,
+                b.segwit_share,
+            );
+        }
+        csv
+    }
+}
+
+fn ratio(num: u64, den: u64) -> f64 {
+    match den {
+        0 => 0.0,
+        den => num as f64 / den as f64,
+    }
+}
+
+/// Fetches "getblockstats" for many blocks in JSON-RPC batches and
+/// aggregates them into a [StatsSeries].
+pub struct BlockStatsCollector<'a> {
+    client: &'a Client,
+    batch_size: usize,
+    bucket: Duration,
+}
+
+impl<'a> BlockStatsCollector<'a> {
+    /// Creates a collector fetching 100 blocks per batch into daily buckets.
+    pub fn new(client: &'a Client) -> Self {
+        Self {
+            client,
+            batch_size: 100,
+            bucket: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+
+    /// Sets how many blocks are requested in a single batch.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Sets the duration of the buckets.
+    pub fn bucket(mut self, bucket: Duration) -> Self {
+        self.bucket = bucket;
+        self
+    }
+
+    /// Fetches the statistics of `blocks`, in order.
+    ///
+    /// Fails with the first error returned for any of the blocks.
+    pub async fn fetch(
+        &self,
+        blocks: impl IntoIterator<Item = HashOrHeight>,
+    ) -> Result<Vec<GetBlockStatsResult>> {
+        let args: Vec<[Value; 1]> = blocks
+            .into_iter()
+            .map(|b| serde_json::to_value(b).map(|v| [v]))
+            .collect::<std::result::Result<_, _>>()?;
+        let mut stats = Vec::with_capacity(args.len());
+        for chunk in args.chunks(self.batch_size) {
+            let calls: Vec<(&str, &[Value])> = chunk
+                .iter()
+                .map(|a| ("getblockstats", a.as_slice()))
+                .collect();
+            for result in self.client.call_batch(&calls).await? {
+                stats.push(result?);
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Fetches the statistics of the blocks at `heights` and aggregates them.
+    pub async fn collect(&self, heights: RangeInclusive<u64>) -> Result<StatsSeries> {
+        let stats = self.fetch(heights.map(HashOrHeight::Height)).await?;
+        Ok(StatsSeries::aggregate(&stats, self.bucket))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn block(height: u64, time: u64, txs: u64, sw_txs: u64, fee: u64, p50: u64) -> Value {
+        json!({
+            "avgfee": 0, "avgfeerate": 0, "avgtxsize": 0, "ins": 0, "outs": 0,
+            "blockhash": "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206",
+            "feerate_percentiles": [1, 2, p50, 4, 5],
+            "height": height, "time": time, "mediantime": time,
+            "maxfee": 0, "maxfeerate": 0, "maxtxsize": 0, "medianfee": 0,
+            "mediantxsize": 0, "minfee": 0, "minfeerate": 0, "mintxsize": 0,
+            "subsidy": 312500000, "swtotal_size": 0, "swtotal_weight": 0,
+            "swtxs": sw_txs, "total_out": 0, "total_size": 0,
+            "total_weight": (txs - 1) * 1000, "totalfee": fee, "txs": txs,
+            "utxo_increase": 0, "utxo_size_inc": 0,
+        })
+    }
+
+    #[test]
+    fn aggregates_into_buckets() {
+        let stats: Vec<GetBlockStatsResult> = [
+            block(100, 3_600, 11, 5, 10_000, 10),
+            block(101, 4_000, 31, 30, 30_000, 30),
+            block(102, 7_300, 1, 0, 0, 0),
+        ]
+        .into_iter()
+        .map(|v| serde_json::from_value(v).unwrap())
+        .collect();
+        let series = StatsSeries::aggregate(&stats, Duration::from_secs(3_600));
+        assert_eq!(series.buckets.len(), 2);
+
+        let first = &series.buckets[0];
+        assert_eq!((first.start, first.blocks, first.txs), (3_600, 2, 40));
+        assert_eq!((first.first_height, first.last_height), (100, 101));
+        assert_eq!(first.total_fee, Amount::from_sat(40_000));
+        assert_eq!(first.avg_fee_rate, 4.0);
+        assert_eq!(first.fee_rate_percentiles[2], 25.0);
+        assert_eq!(first.segwit_share, 35.0 / 40.0);
+
+        let empty = &series.buckets[1];
+        assert_eq!(
+            (empty.start, empty.txs, empty.segwit_share),
+            (7_200, 0, 0.0)
+        );
+
+        let csv = series.to_csv();
+        assert_eq!(csv.lines().count(), 3);
+        assert!(csv
+            .lines()
+            .nth(1)
+            .unwrap()
+            .starts_with("3600,2,100,101,40,40000,4.000,"));
+    }
+}
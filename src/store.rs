@@ -0,0 +1,221 @@
+//! A persistent store of raw blocks and headers, enabled by the `store`
+//! feature.
+//!
+//! Blocks and headers are kept by hash in a [redb] database, along with an
+//! index of the block hash at each height. A
+//! [Client](crate::client::Client) configured with
+//! [Client::store](crate::client::Client::store) reads blocks and headers
+//! through the store, so that anything fetched once can later be read
+//! offline.
+
+use std::{ops::RangeBounds, path::Path, sync::Arc};
+
+use bitcoincore_rpc_json::bitcoin::{
+    block::Header, consensus::encode, hashes::Hash, Block, BlockHash,
+};
+use redb::{Database, ReadableTable, TableDefinition};
+
+use crate::{client::Result, error::Error};
+
+const BLOCKS: TableDefinition<&[u8], &[u8]> = TableDefinition::new("blocks");
+const HEADERS: TableDefinition<&[u8], &[u8]> = TableDefinition::new("headers");
+const HEIGHTS: TableDefinition<u64, &[u8]> = TableDefinition::new("heights");
+
+/// Decodes a block hash from the height index.
+fn decode_hash(bytes: &[u8]) -> Result<BlockHash> {
+    BlockHash::from_slice(bytes).map_err(|_| {
+        let msg = format!("block hash of {} bytes in the height index", bytes.len());
+        Error::Store(Box::new(redb::Error::Corrupted(msg)))
+    })
+}
+
+/// Converts any of the redb errors.
+fn try_db<T, E: Into<redb::Error>>(res: std::result::Result<T, E>) -> Result<T> {
+    res.map_err(|e| Error::Store(Box::new(e.into())))
+}
+
+/// Raw blocks and headers by hash, and block hashes by height, on disk.
+///
+/// Writes are synchronous and durable once the call returns, and skipped
+/// when the value is already stored. From async code, go through
+/// [BlockStore::run] so as not to block the runtime.
+pub struct BlockStore {
+    db: Database,
+}
+
+impl BlockStore {
+    /// Opens the store at `path`, creating it if needed.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let db = try_db(Database::create(path))?;
+        let txn = try_db(db.begin_write())?;
+        try_db(txn.open_table(BLOCKS))?;
+        try_db(txn.open_table(HEADERS))?;
+        try_db(txn.open_table(HEIGHTS))?;
+        try_db(txn.commit())?;
+        Ok(Self { db })
+    }
+
+    /// Runs `f` with the store on the blocking thread pool of the Tokio
+    /// runtime, as its transactions wait on disk I/O.
+    pub async fn run<T: Send + 'static>(
+        self: &Arc<Self>,
+        f: impl FnOnce(&Self) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let store = self.clone();
+        tokio::task::spawn_blocking(move || f(&store)).await?
+    }
+
+    /// Returns the serialized block with `hash`, if stored.
+    pub fn block_bytes(&self, hash: &BlockHash) -> Result<Option<Vec<u8>>> {
+        let txn = try_db(self.db.begin_read())?;
+        let table = try_db(txn.open_table(BLOCKS))?;
+        let block = try_db(table.get(hash.as_byte_array().as_slice()))?;
+        Ok(block.map(|b| b.value().to_vec()))
+    }
+
+    /// Returns the block with `hash`, if stored.
+    pub fn block(&self, hash: &BlockHash) -> Result<Option<Block>> {
+        self.block_bytes(hash)?
+            .map(|b| Ok(encode::deserialize(&b)?))
+            .transpose()
+    }
+
+    /// Stores the serialized `block`, with its header, checking that it
+    /// decodes to the block with `hash`.
+    pub fn put_block_bytes(&self, hash: &BlockHash, block: &[u8]) -> Result<()> {
+        let header: Header = encode::deserialize_partial(block)?.0;
+        if header.block_hash() != *hash {
+            return Err(Error::HeaderMismatch {
+                expected: *hash,
+                found: header.block_hash(),
+            });
+        }
+        let txn = try_db(self.db.begin_write())?;
+        {
+            let mut blocks = try_db(txn.open_table(BLOCKS))?;
+            try_db(blocks.insert(hash.as_byte_array().as_slice(), block))?;
+            let mut headers = try_db(txn.open_table(HEADERS))?;
+            try_db(headers.insert(hash.as_byte_array().as_slice(), &block[..80]))?;
+        }
+        try_db(txn.commit())
+    }
+
+    /// Stores `block` and its header.
+    pub fn put_block(&self, block: &Block) -> Result<()> {
+        self.put_block_bytes(&block.block_hash(), &encode::serialize(block))
+    }
+
+    /// Returns the header of the block with `hash`, if stored.
+    pub fn header(&self, hash: &BlockHash) -> Result<Option<Header>> {
+        let txn = try_db(self.db.begin_read())?;
+        let table = try_db(txn.open_table(HEADERS))?;
+        let header = try_db(table.get(hash.as_byte_array().as_slice()))?;
+        header
+            .map(|h| Ok(encode::deserialize(h.value())?))
+            .transpose()
+    }
+
+    /// Stores `header`, unless already stored.
+    pub fn put_header(&self, header: &Header) -> Result<()> {
+        if self.header(&header.block_hash())?.is_some() {
+            return Ok(());
+        }
+        let txn = try_db(self.db.begin_write())?;
+        {
+            let mut headers = try_db(txn.open_table(HEADERS))?;
+            let bytes = encode::serialize(header);
+            try_db(headers.insert(
+                header.block_hash().as_byte_array().as_slice(),
+                bytes.as_slice(),
+            ))?;
+        }
+        try_db(txn.commit())
+    }
+
+    /// Returns the hash of the block recorded at `height`.
+    pub fn hash_at(&self, height: u64) -> Result<Option<BlockHash>> {
+        let txn = try_db(self.db.begin_read())?;
+        let table = try_db(txn.open_table(HEIGHTS))?;
+        let hash = try_db(table.get(height))?;
+        hash.map(|h| decode_hash(h.value())).transpose()
+    }
+
+    /// Records `hash` as the block at `height`. If another block was
+    /// recorded there, the node switched branches, and the heights above are
+    /// forgotten too, so that [Self::tip] stays on the new branch.
+    pub fn set_hash_at(&self, height: u64, hash: &BlockHash) -> Result<()> {
+        let previous = self.hash_at(height)?;
+        if previous == Some(*hash) {
+            return Ok(());
+        }
+        let txn = try_db(self.db.begin_write())?;
+        {
+            let mut heights = try_db(txn.open_table(HEIGHTS))?;
+            if previous.is_some() {
+                try_db(heights.retain_in::<u64, _>(height + 1.., |_, _| false))?;
+            }
+            try_db(heights.insert(height, hash.as_byte_array().as_slice()))?;
+        }
+        try_db(txn.commit())
+    }
+
+    /// Forgets the blocks recorded at `heights`, e.g. those disconnected by
+    /// a reorg. The blocks themselves are kept.
+    pub fn remove_heights(&self, heights: impl RangeBounds<u64>) -> Result<()> {
+        let txn = try_db(self.db.begin_write())?;
+        {
+            let mut table = try_db(txn.open_table(HEIGHTS))?;
+            try_db(table.retain_in::<u64, _>(heights, |_, _| false))?;
+        }
+        try_db(txn.commit())
+    }
+
+    /// Returns the highest height recorded, with its block hash.
+    pub fn tip(&self) -> Result<Option<(u64, BlockHash)>> {
+        let txn = try_db(self.db.begin_read())?;
+        let table = try_db(txn.open_table(HEIGHTS))?;
+        let last = try_db(table.last())?;
+        last.map(|(height, hash)| Ok((height.value(), decode_hash(hash.value())?)))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc_json::bitcoin::{constants::genesis_block, Network};
+
+    #[test]
+    fn stores_blocks_headers_and_heights() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blocks.redb");
+        let genesis = genesis_block(Network::Regtest);
+        let hash = genesis.block_hash();
+        {
+            let store = BlockStore::open(&path).unwrap();
+            assert!(store.block(&hash).unwrap().is_none());
+            assert!(store.tip().unwrap().is_none());
+            store.put_block(&genesis).unwrap();
+            store.set_hash_at(0, &hash).unwrap();
+            store.set_hash_at(1, &BlockHash::all_zeros()).unwrap();
+
+            let mainnet = genesis_block(Network::Bitcoin);
+            let bytes = encode::serialize(&mainnet);
+            assert!(store.put_block_bytes(&hash, &bytes).is_err());
+        }
+
+        let store = BlockStore::open(&path).unwrap();
+        assert_eq!(store.block(&hash).unwrap(), Some(genesis.clone()));
+        assert_eq!(store.header(&hash).unwrap(), Some(genesis.header));
+        assert_eq!(store.tip().unwrap(), Some((1, BlockHash::all_zeros())));
+        store.remove_heights(1..).unwrap();
+        assert_eq!(store.tip().unwrap(), Some((0, hash)));
+        assert_eq!(store.hash_at(0).unwrap(), Some(hash));
+
+        // Another block at a recorded height drops the stale branch above.
+        store.set_hash_at(1, &hash).unwrap();
+        store.set_hash_at(2, &hash).unwrap();
+        store.set_hash_at(1, &BlockHash::all_zeros()).unwrap();
+        assert_eq!(store.tip().unwrap(), Some((1, BlockHash::all_zeros())));
+    }
+}
@@ -0,0 +1,355 @@
+use bitcoincore_rpc_json::bitcoin::{
+    absolute::LockTime, hashes::Hash, transaction, Amount, FeeRate, OutPoint, Psbt, ScriptBuf,
+    Sequence, Transaction, TxIn, TxOut, Weight, Witness,
+};
+
+use crate::{
+    client::Result,
+    coin_selection::{
+        fee_for, satisfaction_weight, BranchAndBound, Candidate, CoinSelection, Knapsack,
+        SelectionTarget, TXIN_BASE_WEIGHT,
+    },
+    error::Error,
+};
+
+/// Weight of the segwit marker and flag.
+const SEGWIT_MARKER_WEIGHT: Weight = Weight::from_wu(2);
+
+/// A funded, unsigned transaction and how it was put together.
+#[derive(Clone, Debug)]
+pub struct BuiltTx {
+    /// The transaction, with the spent outputs of segwit inputs filled in.
+    /// Pass it to `walletprocesspsbt` to sign.
+    pub psbt: Psbt,
+    /// The spent candidates, in input order.
+    pub selected: Vec<Candidate>,
+    /// Name of the coin selection algorithm that found the inputs.
+    pub algorithm: &'static str,
+    /// Index of the change output, if any.
+    pub change_vout: Option<u32>,
+    pub fee: Amount,
+    /// Expected weight of the signed transaction.
+    pub weight: Weight,
+}
+
+/// Funds transactions from `listunspent` outputs with local coin selection.
+///
+/// Unlike `fundrawtransaction` and `walletcreatefundedpsbt`, every step is
+/// deterministic: candidates are filtered and ordered by outpoint, the
+/// selection algorithms are tried in order, and inputs and outputs of the
+/// result are sorted as in BIP69. Fees are computed from the weight of each
+/// input type rather than estimated by the wallet.
+pub struct TxBuilder {
+    candidates: Vec<Candidate>,
+    recipients: Vec<TxOut>,
+    change_script: ScriptBuf,
+    fee_rate: FeeRate,
+    long_term_fee_rate: FeeRate,
+    min_confirmations: u32,
+    algorithms: Vec<Box<dyn CoinSelection>>,
+    lock_time: LockTime,
+}
+
+impl TxBuilder {
+    /// Creates a builder spending from `candidates` and sending change to
+    /// `change_script`.
+    ///
+    /// Selection tries branch and bound first, for a transaction without
+    /// change, then knapsack.
+    pub fn new(mut candidates: Vec<Candidate>, change_script: ScriptBuf) -> Self {
+        candidates.sort_by_key(|c| c.outpoint);
+        candidates.dedup_by_key(|c| c.outpoint);
+        Self {
+            candidates,
+            recipients: Vec::new(),
+            change_script,
+            fee_rate: FeeRate::BROADCAST_MIN,
+            long_term_fee_rate: FeeRate::from_sat_per_vb_unchecked(10),
+            min_confirmations: 1,
+            algorithms: vec![
+                Box::new(BranchAndBound::default()),
+                Box::new(Knapsack::default()),
+            ],
+            lock_time: LockTime::ZERO,
+        }
+    }
+
+    /// Pays `amount` to `script_pubkey`.
+    pub fn add_recipient(mut self, script_pubkey: ScriptBuf, amount: Amount) -> Self {
+        self.recipients.push(TxOut {
+            value: amount,
+            script_pubkey,
+        });
+        self
+    }
+
+    pub fn fee_rate(mut self, fee_rate: FeeRate) -> Self {
+        self.fee_rate = fee_rate;
+        self
+    }
+
+    /// Sets the fee rate expected when the change is spent later.
+    pub fn long_term_fee_rate(mut self, fee_rate: FeeRate) -> Self {
+        self.long_term_fee_rate = fee_rate;
+        self
+    }
+
+    /// Only spends candidates with at least `confirmations` confirmations.
+    pub fn min_confirmations(mut self, confirmations: u32) -> Self {
+        self.min_confirmations = confirmations;
+        self
+    }
+
+    /// Uses `algorithm` as the only coin selection algorithm.
+    pub fn coin_selection(mut self, algorithm: impl CoinSelection + 'static) -> Self {
+        self.algorithms = vec![Box::new(algorithm)];
+        self
+    }
+
+    /// Tries `algorithm` if the previous algorithms found no selection.
+    pub fn fallback(mut self, algorithm: impl CoinSelection + 'static) -> Self {
+        self.algorithms.push(Box::new(algorithm));
+        self
+    }
+
+    pub fn lock_time(mut self, lock_time: LockTime) -> Self {
+        self.lock_time = lock_time;
+        self
+    }
+
+    /// Selects inputs and builds the transaction.
+    pub fn build(&self) -> Result<BuiltTx> {
+        let candidates: Vec<Candidate> = self
+            .candidates
+            .iter()
+            .filter(|c| c.confirmations >= self.min_confirmations)
+            .cloned()
+            .collect();
+
+        let change = TxOut {
+            value: Amount::ZERO,
+            script_pubkey: self.change_script.clone(),
+        };
+        let change_spend_weight = TXIN_BASE_WEIGHT
+            + satisfaction_weight(&self.change_script).unwrap_or(Weight::from_wu(0));
+        let change_fee = fee_for(self.fee_rate, change.weight());
+        let cost_of_change = change_fee + fee_for(self.long_term_fee_rate, change_spend_weight);
+
+        // Assume a witness, up to 252 inputs and up to 3 weight units of
+        // rounding to virtual bytes.
+        let fixed_weight = tx_weight(&[], &self.recipients, true) + Weight::from_wu(3);
+        let send: Amount = self.recipients.iter().map(|o| o.value).sum();
+        let needed = send + fee_for(self.fee_rate, fixed_weight);
+        let target = SelectionTarget {
+            amount: needed,
+            fee_rate: self.fee_rate,
+            long_term_fee_rate: self.long_term_fee_rate,
+            cost_of_change,
+        };
+
+        let insufficient = || Error::InsufficientFunds {
+            needed,
+            available: Amount::from_sat(
+                candidates
+                    .iter()
+                    .map(|c| c.effective_value(self.fee_rate).max(0) as u64)
+                    .sum(),
+            ),
+        };
+        let (algorithm, selection) = self
+            .algorithms
+            .iter()
+            .find_map(|a| a.select(&candidates, &target).map(|s| (a.name(), s)))
+            .ok_or_else(insufficient)?;
+
+        let mut selected: Vec<Candidate> = selection
+            .into_iter()
+            .map(|i| candidates[i].clone())
+            .collect();
+        selected.sort_by_key(|c| bip69_outpoint(&c.outpoint));
+
+        let input_value: Amount = selected.iter().map(|c| c.txout.value).sum();
+        let mut outputs = self.recipients.clone();
+        let fee_without_change =
+            fee_for_vsize(self.fee_rate, tx_weight(&selected, &outputs, false));
+        let excess = input_value
+            .checked_sub(send + fee_without_change)
+            .ok_or_else(insufficient)?;
+
+        let mut fee = excess + fee_without_change;
+        if excess > change_fee {
+            let mut with_change = outputs.clone();
+            with_change.push(change.clone());
+            let fee_with_change =
+                fee_for_vsize(self.fee_rate, tx_weight(&selected, &with_change, false));
+            let change_value = input_value - send - fee_with_change.min(input_value - send);
+            if change_value >= self.change_script.minimal_non_dust() {
+                outputs = with_change;
+                outputs.last_mut().expect("change was pushed").value = change_value;
+                fee = fee_with_change;
+            }
+        }
+        outputs.sort_by(|a, b| {
+            a.value
+                .cmp(&b.value)
+                .then_with(|| a.script_pubkey.cmp(&b.script_pubkey))
+        });
+        let change_vout = (outputs.len() > self.recipients.len())
+            .then(|| {
+                outputs
+                    .iter()
+                    .rposition(|o| o.script_pubkey == self.change_script)
+                    .map(|i| i as u32)
+            })
+            .flatten();
+
+        let weight = tx_weight(&selected, &outputs, false);
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: self.lock_time,
+            input: selected
+                .iter()
+                .map(|c| TxIn {
+                    previous_output: c.outpoint,
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: outputs,
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).expect("inputs are unsigned");
+        for (input, candidate) in psbt.inputs.iter_mut().zip(&selected) {
+            if candidate.is_segwit() {
+                input.witness_utxo = Some(candidate.txout.clone());
+            }
+        }
+
+        Ok(BuiltTx {
+            psbt,
+            selected,
+            algorithm,
+            change_vout,
+            fee,
+            weight,
+        })
+    }
+}
+
+/// Expected weight of a signed transaction spending `inputs` to `outputs`.
+fn tx_weight(inputs: &[Candidate], outputs: &[TxOut], assume_segwit: bool) -> Weight {
+    let header = 4 + 4 + varint_len(inputs.len()) + varint_len(outputs.len());
+    let mut weight = Weight::from_non_witness_data_size(header as u64);
+    weight += outputs.iter().map(TxOut::weight).sum();
+    weight += inputs.iter().map(Candidate::weight).sum();
+
+    let legacy_inputs = inputs.iter().filter(|c| !c.is_segwit()).count() as u64;
+    if assume_segwit || legacy_inputs < inputs.len() as u64 {
+        // Legacy inputs of a segwit transaction carry an empty witness.
+        weight += SEGWIT_MARKER_WEIGHT + Weight::from_witness_data_size(legacy_inputs);
+    }
+    weight
+}
+
+fn varint_len(n: usize) -> usize {
+    match n {
+        0..=0xfc => 1,
+        0xfd..=0xffff => 3,
+        _ => 5,
+    }
+}
+
+/// Fee for a transaction of `weight` at `fee_rate`, charged per virtual byte
+/// as the node does.
+fn fee_for_vsize(fee_rate: FeeRate, weight: Weight) -> Amount {
+    fee_for(fee_rate, Weight::from_vb_unchecked(weight.to_vbytes_ceil()))
+}
+
+/// Sort key of an outpoint under BIP69, which compares txids in the byte
+/// order they are displayed in.
+fn bip69_outpoint(outpoint: &OutPoint) -> ([u8; 32], u32) {
+    let mut txid = outpoint.txid.to_byte_array();
+    txid.reverse();
+    (txid, outpoint.vout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coin_selection::LargestFirst;
+    use bitcoincore_rpc_json::bitcoin::Txid;
+
+    fn p2wpkh(tag: u8) -> ScriptBuf {
+        let mut bytes = vec![0x00, 0x14];
+        bytes.extend([tag; 20]);
+        ScriptBuf::from_bytes(bytes)
+    }
+
+    fn candidate(vout: u32, sats: u64) -> Candidate {
+        Candidate {
+            outpoint: OutPoint::new(Txid::all_zeros(), vout),
+            txout: TxOut {
+                value: Amount::from_sat(sats),
+                script_pubkey: p2wpkh(vout as u8),
+            },
+            satisfaction_weight: satisfaction_weight(&p2wpkh(0)).unwrap(),
+            confirmations: 6,
+        }
+    }
+
+    #[test]
+    fn weight_of_p2wpkh_spend() {
+        let outputs = [
+            TxOut {
+                value: Amount::ZERO,
+                script_pubkey: p2wpkh(1),
+            },
+            TxOut {
+                value: Amount::ZERO,
+                script_pubkey: p2wpkh(2),
+            },
+        ];
+        // 1-in 2-out P2WPKH: 140.5 vB.
+        let weight = tx_weight(&[candidate(0, 0)], &outputs, false);
+        assert_eq!(weight, Weight::from_wu(562));
+        assert_eq!(weight.to_vbytes_ceil(), 141);
+    }
+
+    #[test]
+    fn adds_change_when_worth_it() {
+        let rate = FeeRate::from_sat_per_vb_unchecked(2);
+        let built = TxBuilder::new(vec![candidate(0, 100_000)], p2wpkh(9))
+            .add_recipient(p2wpkh(7), Amount::from_sat(40_000))
+            .fee_rate(rate)
+            .coin_selection(LargestFirst)
+            .build()
+            .unwrap();
+
+        let tx = &built.psbt.unsigned_tx;
+        assert_eq!(built.algorithm, "largest-first");
+        assert_eq!(built.fee, Amount::from_sat(282));
+        assert_eq!(tx.output.len(), 2);
+        let change = &tx.output[built.change_vout.unwrap() as usize];
+        assert_eq!(change.value, Amount::from_sat(100_000 - 40_000 - 282));
+        assert!(built.psbt.inputs[0].witness_utxo.is_some());
+    }
+
+    #[test]
+    fn changeless_when_excess_is_small() {
+        let rate = FeeRate::from_sat_per_vb_unchecked(1);
+        let built = TxBuilder::new(vec![candidate(0, 40_200)], p2wpkh(9))
+            .add_recipient(p2wpkh(7), Amount::from_sat(40_000))
+            .fee_rate(rate)
+            .build()
+            .unwrap();
+        assert_eq!(built.change_vout, None);
+        assert_eq!(built.psbt.unsigned_tx.output.len(), 1);
+        assert_eq!(built.fee, Amount::from_sat(200));
+
+        let err = TxBuilder::new(vec![candidate(0, 40_000)], p2wpkh(9))
+            .add_recipient(p2wpkh(7), Amount::from_sat(40_000))
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, Error::InsufficientFunds { .. }));
+    }
+}
@@ -0,0 +1,180 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use bitcoincore_rpc_json::bitcoin::{Block, BlockHash, Txid};
+
+use crate::{
+    client::Result,
+    error::Error,
+    indexer::{BlockIndex, Indexer},
+};
+
+/// Where a transaction was confirmed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TxLocation {
+    pub block_hash: BlockHash,
+    pub height: u64,
+    /// Index of the transaction in its block.
+    pub position: usize,
+}
+
+/// An in-memory index of the block of each transaction, for nodes running
+/// without `-txindex`.
+///
+/// The index is maintained by a [TxIndexer], and can be given to
+/// [Client::tx_index](crate::client::Client::tx_index) so that transaction
+/// lookups are sent with the block hash.
+#[derive(Default)]
+pub struct TxIndex {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    txs: HashMap<Txid, TxLocation>,
+    /// The txids of each indexed block, to undo it on a reorg.
+    blocks: HashMap<BlockHash, Vec<Txid>>,
+}
+
+impl TxIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns where `txid` was confirmed, if indexed.
+    pub fn location(&self, txid: &Txid) -> Option<TxLocation> {
+        self.inner.lock().expect("poisoned").txs.get(txid).copied()
+    }
+
+    /// Number of transactions indexed.
+    pub fn len(&self) -> usize {
+        self.inner.lock().expect("poisoned").txs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of blocks indexed.
+    pub fn block_count(&self) -> usize {
+        self.inner.lock().expect("poisoned").blocks.len()
+    }
+
+    /// Indexes the transactions of `block`, connected at `height`.
+    pub fn connect_block(&self, height: u64, block: &Block) {
+        let block_hash = block.block_hash();
+        let mut inner = self.inner.lock().expect("poisoned");
+        let mut txids = Vec::with_capacity(block.txdata.len());
+        for (position, tx) in block.txdata.iter().enumerate() {
+            let txid = tx.compute_txid();
+            inner.txs.insert(
+                txid,
+                TxLocation {
+                    block_hash,
+                    height,
+                    position,
+                },
+            );
+            txids.push(txid);
+        }
+        inner.blocks.insert(block_hash, txids);
+    }
+
+    /// Removes the transactions of the block with `hash`, disconnected by a
+    /// reorg.
+    ///
+    /// Transactions indexed since in another block are kept. Returns false
+    /// if the block was not indexed.
+    pub fn disconnect_block(&self, hash: &BlockHash) -> bool {
+        let mut inner = self.inner.lock().expect("poisoned");
+        let Some(txids) = inner.blocks.remove(hash) else {
+            return false;
+        };
+        for txid in txids {
+            if inner.txs.get(&txid).is_some_and(|l| l.block_hash == *hash) {
+                inner.txs.remove(&txid);
+            }
+        }
+        true
+    }
+}
+
+impl BlockIndex for TxIndex {
+    fn block_connected(&self, height: u64, block: &Block) -> Result<()> {
+        self.connect_block(height, block);
+        Ok(())
+    }
+
+    fn block_disconnected(&self, _height: u64, hash: &BlockHash) -> Result<()> {
+        if !self.disconnect_block(hash) {
+            return Err(Error::IndexOutOfSync { hash: *hash });
+        }
+        Ok(())
+    }
+}
+
+/// Maintains a [TxIndex] by following the node's best chain.
+pub type TxIndexer = Indexer<TxIndex>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc_json::bitcoin::{
+        absolute::LockTime, block, constants::genesis_block, hashes::Hash, transaction,
+        CompactTarget, Network, Transaction, TxMerkleNode,
+    };
+
+    fn block_with(time: u32, prev: BlockHash, txdata: Vec<Transaction>) -> Block {
+        Block {
+            header: block::Header {
+                version: block::Version::TWO,
+                prev_blockhash: prev,
+                merkle_root: TxMerkleNode::all_zeros(),
+                time,
+                bits: CompactTarget::from_consensus(0x207fffff),
+                nonce: 0,
+            },
+            txdata,
+        }
+    }
+
+    fn tx(lock_time: u32) -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: LockTime::from_consensus(lock_time),
+            input: vec![],
+            output: vec![],
+        }
+    }
+
+    #[test]
+    fn connects_and_disconnects_blocks() {
+        let genesis = genesis_block(Network::Regtest);
+        let index = TxIndex::new();
+        index.connect_block(0, &genesis);
+        let coinbase = genesis.txdata[0].compute_txid();
+        assert_eq!(
+            index.location(&coinbase).unwrap().block_hash,
+            genesis.block_hash()
+        );
+
+        let stale = block_with(1, genesis.block_hash(), vec![tx(1), tx(2)]);
+        let best = block_with(2, genesis.block_hash(), vec![tx(3), tx(2)]);
+        index.connect_block(1, &stale);
+        assert_eq!(index.len(), 3);
+        let moved = tx(2).compute_txid();
+        assert_eq!(index.location(&moved).unwrap().position, 1);
+
+        // The reorg connects the new branch after disconnecting the old one,
+        // but a transaction indexed again must survive either order.
+        index.connect_block(1, &best);
+        assert!(index.disconnect_block(&stale.block_hash()));
+        assert_eq!(index.block_count(), 2);
+        assert_eq!(index.len(), 3);
+        assert!(index.location(&tx(1).compute_txid()).is_none());
+        let location = index.location(&moved).unwrap();
+        assert_eq!(
+            (location.block_hash, location.height),
+            (best.block_hash(), 1)
+        );
+    }
+}
@@ -0,0 +1,868 @@
+//! Result and option types for RPCs that `bitcoincore-rpc-json` does not
+//! cover.
+
+use std::collections::HashMap;
+
+use bitcoincore_rpc_json::{
+    bitcoin::{
+        address::NetworkUnchecked, consensus::encode, hex::DisplayHex, Address, Amount, BlockHash,
+        FeeRate, Transaction, Txid,
+    },
+    AddressType, CreateRawTransactionInput, EstimateMode, GetAddressInfoResultLabelPurpose,
+};
+use serde::{ser::SerializeMap, Deserialize, Serialize, Serializer};
+
+/// Progress of a running `scantxoutset` scan.
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct ScanTxOutStatus {
+    /// Approximate percentage of the UTXO set scanned so far.
+    pub progress: f64,
+}
+
+/// Models the result of "estimaterawfee".
+///
+/// Horizons that do not track the requested target are omitted.
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct EstimateRawFeeResult {
+    pub short: Option<RawFeeEstimate>,
+    pub medium: Option<RawFeeEstimate>,
+    pub long: Option<RawFeeEstimate>,
+}
+
+impl EstimateRawFeeResult {
+    /// Returns the fee rate of the shortest horizon that produced one.
+    pub fn fee_rate(&self) -> Option<Amount> {
+        [&self.short, &self.medium, &self.long]
+            .into_iter()
+            .flatten()
+            .find_map(|h| h.fee_rate)
+    }
+}
+
+/// Raw fee estimate of a single horizon.
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct RawFeeEstimate {
+    /// Estimated fee rate in BTC/kvB.
+    #[serde(
+        default,
+        rename = "feerate",
+        skip_serializing_if = "Option::is_none",
+        with = "bitcoincore_rpc_json::bitcoin::amount::serde::as_btc::opt"
+    )]
+    pub fee_rate: Option<Amount>,
+    /// Exponential decay (per block) for historical moving average of
+    /// confirmation data.
+    pub decay: f64,
+    /// The resolution of confirmation targets at this time horizon.
+    pub scale: u32,
+    /// Information about the lowest range of fee rates to succeed in meeting
+    /// the threshold.
+    pub pass: Option<RawFeeBucket>,
+    /// Information about the highest range of fee rates to fail to meet the
+    /// threshold.
+    pub fail: Option<RawFeeBucket>,
+    /// Errors encountered during processing.
+    #[serde(default)]
+    pub errors: Vec<String>,
+}
+
+/// Statistics of a fee rate range tracked by the fee estimator.
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct RawFeeBucket {
+    /// Start of the fee rate range, in sat/kvB.
+    #[serde(rename = "startrange")]
+    pub start_range: f64,
+    /// End of the fee rate range, in sat/kvB.
+    #[serde(rename = "endrange")]
+    pub end_range: f64,
+    /// Number of txs over history horizon in the fee rate range that were
+    /// confirmed within target.
+    #[serde(rename = "withintarget")]
+    pub within_target: f64,
+    /// Number of txs over history horizon in the fee rate range that were
+    /// confirmed at any point.
+    #[serde(rename = "totalconfirmed")]
+    pub total_confirmed: f64,
+    /// Current number of txs in mempool in the fee rate range unconfirmed for
+    /// at least target blocks.
+    #[serde(rename = "inmempool")]
+    pub in_mempool: f64,
+    /// Number of txs over history horizon in the fee rate range that left the
+    /// mempool unconfirmed after target.
+    #[serde(rename = "leftmempool")]
+    pub left_mempool: f64,
+}
+
+/// Options for "bumpfee" and "psbtbumpfee".
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize)]
+pub struct BumpFeeOptions {
+    /// Confirmation target in blocks.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conf_target: Option<u16>,
+    /// Fee rate to pay, instead of relying on the wallet's estimator.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opt_sat_per_vb"
+    )]
+    pub fee_rate: Option<FeeRate>,
+    /// Whether the new transaction should signal BIP125 replaceability.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replaceable: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimate_mode: Option<EstimateMode>,
+    /// Index of the change output of the original transaction to recycle.
+    /// Added in Core v26.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_change_index: Option<u32>,
+}
+
+/// Models the result of "bumpfee".
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct BumpFeeResult {
+    /// The id of the replacement transaction.
+    pub txid: Txid,
+    /// The fee of the replaced transaction.
+    #[serde(
+        rename = "origfee",
+        with = "bitcoincore_rpc_json::bitcoin::amount::serde::as_btc"
+    )]
+    pub original_fee: Amount,
+    /// The fee of the replacement transaction.
+    #[serde(with = "bitcoincore_rpc_json::bitcoin::amount::serde::as_btc")]
+    pub fee: Amount,
+    /// Errors encountered during processing.
+    #[serde(default)]
+    pub errors: Vec<String>,
+}
+
+/// Models the result of "psbtbumpfee".
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct PsbtBumpFeeResult {
+    /// The base64-encoded unsigned PSBT of the replacement transaction.
+    pub psbt: String,
+    /// The fee of the replaced transaction.
+    #[serde(
+        rename = "origfee",
+        with = "bitcoincore_rpc_json::bitcoin::amount::serde::as_btc"
+    )]
+    pub original_fee: Amount,
+    /// The fee of the replacement transaction.
+    #[serde(with = "bitcoincore_rpc_json::bitcoin::amount::serde::as_btc")]
+    pub fee: Amount,
+    /// Errors encountered during processing.
+    #[serde(default)]
+    pub errors: Vec<String>,
+}
+
+/// Serializes a fee rate in sat/vB, the unit of the wallet RPCs' `fee_rate`
+/// options.
+fn serialize_opt_sat_per_vb<S: Serializer>(
+    fee_rate: &Option<FeeRate>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match fee_rate {
+        Some(rate) => serializer.serialize_f64(rate.to_sat_per_kwu() as f64 / 250.0),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Models an entry of the result of "gettxspendingprevout".
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct TxSpendingPrevOutResult {
+    pub txid: Txid,
+    pub vout: u32,
+    /// The mempool transaction spending the output, if any.
+    #[serde(rename = "spendingtxid")]
+    pub spending_txid: Option<Txid>,
+}
+
+/// Request for "sendmany", paying several addresses in one transaction.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct SendManyRequest {
+    pub amounts: Vec<(Address, Amount)>,
+    /// Recipients paying the fee out of their amount, in equal shares.
+    pub subtract_fee_from: Vec<Address>,
+    /// Only spend outputs with at least this many confirmations.
+    pub min_conf: Option<u32>,
+    pub comment: Option<String>,
+    pub replaceable: Option<bool>,
+    pub conf_target: Option<u16>,
+    pub estimate_mode: Option<EstimateMode>,
+    /// Fee rate to pay, instead of relying on the wallet's estimator.
+    pub fee_rate: Option<FeeRate>,
+}
+
+impl SendManyRequest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pays `amount` to `address`.
+    pub fn recipient(mut self, address: Address, amount: Amount) -> Self {
+        self.amounts.push((address, amount));
+        self
+    }
+
+    /// Deducts part of the fee from the amount paid to `address`.
+    pub fn subtract_fee_from(mut self, address: Address) -> Self {
+        self.subtract_fee_from.push(address);
+        self
+    }
+
+    pub fn min_conf(mut self, min_conf: u32) -> Self {
+        self.min_conf = Some(min_conf);
+        self
+    }
+
+    pub fn comment(mut self, comment: impl Into<String>) -> Self {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    pub fn replaceable(mut self, replaceable: bool) -> Self {
+        self.replaceable = Some(replaceable);
+        self
+    }
+
+    pub fn conf_target(mut self, conf_target: u16, estimate_mode: Option<EstimateMode>) -> Self {
+        self.conf_target = Some(conf_target);
+        self.estimate_mode = estimate_mode;
+        self
+    }
+
+    pub fn fee_rate(mut self, fee_rate: FeeRate) -> Self {
+        self.fee_rate = Some(fee_rate);
+        self
+    }
+}
+
+/// An output of "send".
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SendOutput {
+    Address(Address, Amount),
+    /// An OP_RETURN output carrying the data.
+    Data(Vec<u8>),
+}
+
+impl Serialize for SendOutput {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
+        match self {
+            SendOutput::Address(address, amount) => {
+                map.serialize_entry(&address.to_string(), &amount.to_btc())?
+            }
+            SendOutput::Data(data) => map.serialize_entry("data", &data.to_lower_hex_string())?,
+        }
+        map.end()
+    }
+}
+
+/// Options shared by "send" and "sendall".
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize)]
+pub struct SendOptions {
+    /// Spend these outputs. "send" may add more unless `add_inputs` is false.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<CreateRawTransactionInput>,
+    /// Whether "send" may add inputs beyond `inputs`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub add_inputs: Option<bool>,
+    /// Whether to add the transaction to the wallet and broadcast it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub add_to_wallet: Option<bool>,
+    /// Address to send the change to, for "send".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change_address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change_position: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change_type: Option<AddressType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conf_target: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimate_mode: Option<EstimateMode>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opt_sat_per_vb"
+    )]
+    pub fee_rate: Option<FeeRate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include_watching: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locktime: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lock_unspents: Option<bool>,
+    /// Always return a PSBT, even when the transaction is complete.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub psbt: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replaceable: Option<bool>,
+    /// Indices of the outputs paying the fee out of their amount, for "send".
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub subtract_fee_from_outputs: Vec<u32>,
+    /// Spend every output with a positive effective value and ignore
+    /// `inputs`, for "sendall".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub send_max: Option<bool>,
+    /// Only spend outputs with at least this many confirmations, for
+    /// "sendall".
+    #[serde(rename = "minconf", skip_serializing_if = "Option::is_none")]
+    pub min_conf: Option<u32>,
+    #[serde(rename = "maxconf", skip_serializing_if = "Option::is_none")]
+    pub max_conf: Option<u32>,
+}
+
+/// Request for "send", the PSBT based successor of "sendmany".
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct SendRequest {
+    pub outputs: Vec<SendOutput>,
+    pub options: SendOptions,
+}
+
+/// Request for "sendall", spending the wallet's funds without change.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct SendAllRequest {
+    /// Recipients with a fixed amount, and the ones sharing what is left.
+    pub recipients: Vec<(Address, Option<Amount>)>,
+    pub options: SendOptions,
+}
+
+macro_rules! impl_send_options {
+    ($request:ty) => {
+        impl $request {
+            /// Spends `input`.
+            pub fn input(mut self, input: CreateRawTransactionInput) -> Self {
+                self.options.inputs.push(input);
+                self
+            }
+
+            /// Whether to add the transaction to the wallet and broadcast it.
+            /// When false, the signed transaction is only returned.
+            pub fn add_to_wallet(mut self, add_to_wallet: bool) -> Self {
+                self.options.add_to_wallet = Some(add_to_wallet);
+                self
+            }
+
+            /// Always returns the transaction as a PSBT.
+            pub fn psbt(mut self, psbt: bool) -> Self {
+                self.options.psbt = Some(psbt);
+                self
+            }
+
+            pub fn conf_target(
+                mut self,
+                conf_target: u16,
+                estimate_mode: Option<EstimateMode>,
+            ) -> Self {
+                self.options.conf_target = Some(conf_target);
+                self.options.estimate_mode = estimate_mode;
+                self
+            }
+
+            pub fn fee_rate(mut self, fee_rate: FeeRate) -> Self {
+                self.options.fee_rate = Some(fee_rate);
+                self
+            }
+
+            pub fn replaceable(mut self, replaceable: bool) -> Self {
+                self.options.replaceable = Some(replaceable);
+                self
+            }
+
+            pub fn locktime(mut self, locktime: u32) -> Self {
+                self.options.locktime = Some(locktime);
+                self
+            }
+
+            pub fn lock_unspents(mut self, lock_unspents: bool) -> Self {
+                self.options.lock_unspents = Some(lock_unspents);
+                self
+            }
+
+            pub fn include_watching(mut self, include_watching: bool) -> Self {
+                self.options.include_watching = Some(include_watching);
+                self
+            }
+        }
+    };
+}
+
+impl_send_options!(SendRequest);
+impl_send_options!(SendAllRequest);
+
+impl SendRequest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pays `amount` to `address`.
+    pub fn recipient(mut self, address: Address, amount: Amount) -> Self {
+        self.outputs.push(SendOutput::Address(address, amount));
+        self
+    }
+
+    /// Adds an OP_RETURN output carrying `data`.
+    pub fn data(mut self, data: Vec<u8>) -> Self {
+        self.outputs.push(SendOutput::Data(data));
+        self
+    }
+
+    /// Deducts part of the fee from the output at `index`.
+    pub fn subtract_fee_from_output(mut self, index: u32) -> Self {
+        self.options.subtract_fee_from_outputs.push(index);
+        self
+    }
+
+    /// Whether the wallet may add inputs beyond the ones given.
+    pub fn add_inputs(mut self, add_inputs: bool) -> Self {
+        self.options.add_inputs = Some(add_inputs);
+        self
+    }
+
+    pub fn change_address(mut self, address: &Address) -> Self {
+        self.options.change_address = Some(address.to_string());
+        self
+    }
+
+    pub fn change_position(mut self, position: u32) -> Self {
+        self.options.change_position = Some(position);
+        self
+    }
+
+    pub fn change_type(mut self, change_type: AddressType) -> Self {
+        self.options.change_type = Some(change_type);
+        self
+    }
+}
+
+impl SendAllRequest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pays a fixed `amount` to `address`.
+    pub fn recipient(mut self, address: Address, amount: Amount) -> Self {
+        self.recipients.push((address, Some(amount)));
+        self
+    }
+
+    /// Pays `address` an equal share of what is left after the fixed
+    /// amounts and the fee.
+    pub fn remainder_to(mut self, address: Address) -> Self {
+        self.recipients.push((address, None));
+        self
+    }
+
+    /// Spends every output with a positive effective value.
+    pub fn send_max(mut self, send_max: bool) -> Self {
+        self.options.send_max = Some(send_max);
+        self
+    }
+
+    pub fn min_conf(mut self, min_conf: u32) -> Self {
+        self.options.min_conf = Some(min_conf);
+        self
+    }
+
+    pub fn max_conf(mut self, max_conf: u32) -> Self {
+        self.options.max_conf = Some(max_conf);
+        self
+    }
+
+    /// The recipients as "sendall" expects them: bare addresses for the
+    /// remainder, objects for fixed amounts.
+    pub(crate) fn recipients_json(&self) -> Vec<serde_json::Value> {
+        self.recipients
+            .iter()
+            .map(|(address, amount)| match amount {
+                None => address.to_string().into(),
+                Some(amount) => serde_json::json!({ address.to_string(): amount.to_btc() }),
+            })
+            .collect()
+    }
+}
+
+/// Models the result of "send" and "sendall".
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct SendResult {
+    /// Whether the transaction is fully signed.
+    pub complete: bool,
+    /// Set when the transaction was added to the wallet.
+    pub txid: Option<Txid>,
+    /// The signed transaction, when complete and not added to the wallet.
+    pub hex: Option<String>,
+    /// The transaction as a base64 PSBT, when incomplete or requested.
+    pub psbt: Option<String>,
+}
+
+impl SendResult {
+    /// Decodes the signed transaction, if returned.
+    pub fn transaction(&self) -> Option<Result<Transaction, encode::FromHexError>> {
+        self.hex.as_deref().map(encode::deserialize_hex)
+    }
+}
+
+/// Models the result of "listdescriptors".
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct ListDescriptorsResult {
+    pub wallet_name: String,
+    pub descriptors: Vec<ListDescriptorsEntry>,
+}
+
+/// A descriptor of a descriptor wallet.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct ListDescriptorsEntry {
+    /// The descriptor, with private keys if they were requested.
+    pub desc: String,
+    /// Time from which the wallet scans for transactions of the descriptor.
+    pub timestamp: u64,
+    /// Whether the descriptor is used to generate new addresses.
+    pub active: bool,
+    /// Whether the descriptor generates change addresses. Only set for
+    /// active descriptors.
+    pub internal: Option<bool>,
+    /// Range of indices derived so far, for ranged descriptors.
+    pub range: Option<[u64; 2]>,
+    /// Next index to derive an address from, for ranged descriptors.
+    pub next_index: Option<u64>,
+}
+
+/// Options for "createwallet". Unset options take the node's default.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct CreateWalletOptions {
+    /// Create a watch-only wallet, without private keys.
+    pub disable_private_keys: Option<bool>,
+    /// Create a wallet without keys or an HD seed.
+    pub blank: Option<bool>,
+    /// Encrypt the wallet with this passphrase.
+    pub passphrase: Option<String>,
+    /// Avoid spending from addresses that were already spent from.
+    pub avoid_reuse: Option<bool>,
+    /// Create a descriptor wallet, the default since v23.
+    pub descriptors: Option<bool>,
+    /// Add the wallet to, or remove it from, the wallets loaded on startup.
+    /// The list is left unchanged if unset.
+    pub load_on_startup: Option<bool>,
+    /// Use an external signer such as a hardware wallet.
+    pub external_signer: Option<bool>,
+}
+
+/// Options for "createwalletdescriptor".
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize)]
+pub struct CreateWalletDescriptorOptions {
+    /// Whether to create only the change descriptor, or only the receive
+    /// descriptor. Both are created if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub internal: Option<bool>,
+    /// The HD key to derive from, as an xpub. Defaults to the wallet's
+    /// single HD key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hdkey: Option<String>,
+}
+
+/// Models the result of "createwalletdescriptor".
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct CreateWalletDescriptorResult {
+    /// The public descriptors that were added to the wallet.
+    pub descs: Vec<String>,
+}
+
+/// Options for "gethdkeys".
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize)]
+pub struct GetHdKeysOptions {
+    /// Only list keys used by active descriptors.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_only: Option<bool>,
+    /// Include the xprv of each key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub private: Option<bool>,
+}
+
+/// An HD key of a descriptor wallet, as listed by "gethdkeys".
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct HdKey {
+    pub xpub: String,
+    pub has_private: bool,
+    /// Only set when private keys were requested.
+    pub xprv: Option<String>,
+    pub descriptors: Vec<HdKeyDescriptor>,
+}
+
+/// A descriptor using an [HdKey].
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct HdKeyDescriptor {
+    pub desc: String,
+    pub active: bool,
+}
+
+/// Models the result of "migratewallet".
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct MigrateWalletResult {
+    /// Name of the migrated wallet.
+    pub wallet_name: String,
+    /// Name of the wallet holding the watch-only scripts, if any.
+    pub watchonly_name: Option<String>,
+    /// Name of the wallet holding the solvable but not watched scripts, if
+    /// any.
+    pub solvables_name: Option<String>,
+    /// Path of the backup taken before migrating.
+    pub backup_path: String,
+}
+
+/// An address of a label, as listed by "getaddressesbylabel".
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct AddressesByLabelEntry {
+    pub purpose: GetAddressInfoResultLabelPurpose,
+}
+
+/// Models an entry of the result of "listreceivedbylabel".
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct ListReceivedByLabelResult {
+    /// Whether watch-only addresses took part in the payments.
+    #[serde(default, rename = "involvesWatchonly")]
+    pub involves_watch_only: bool,
+    /// Total amount received by addresses with this label.
+    #[serde(with = "bitcoincore_rpc_json::bitcoin::amount::serde::as_btc")]
+    pub amount: Amount,
+    /// Confirmations of the most recent payment.
+    pub confirmations: u32,
+    pub label: String,
+}
+
+/// An address of a "listaddressgroupings" group.
+///
+/// The node returns each entry as an `[address, amount, label]` array.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct AddressGrouping {
+    pub address: Address<NetworkUnchecked>,
+    #[serde(with = "bitcoincore_rpc_json::bitcoin::amount::serde::as_btc")]
+    pub amount: Amount,
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+/// Progress of a wallet rescan, from the "scanning" field of
+/// "getwalletinfo".
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize, Serialize)]
+pub struct RescanProgress {
+    /// Seconds elapsed since the rescan started.
+    pub duration: u64,
+    /// Fraction of the blocks scanned so far, from 0 to 1.
+    pub progress: f64,
+}
+
+/// Result of "getrpcinfo".
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct GetRpcInfoResult {
+    /// Commands the server is currently running.
+    pub active_commands: Vec<ActiveCommand>,
+    /// Path of the debug log. Added in v0.20.
+    #[serde(default)]
+    pub logpath: Option<String>,
+}
+
+/// A command running on the server, from "getrpcinfo".
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct ActiveCommand {
+    pub method: String,
+    /// Microseconds the command has been running for.
+    pub duration: u64,
+}
+
+/// Result of "getmemoryinfo" in "stats" mode.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct GetMemoryInfoResult {
+    pub locked: LockedMemoryInfo,
+}
+
+/// Statistics of the secure memory pool, in bytes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct LockedMemoryInfo {
+    pub used: u64,
+    pub free: u64,
+    pub total: u64,
+    /// Memory that could be locked into RAM.
+    pub locked: u64,
+    pub chunks_used: u64,
+    pub chunks_free: u64,
+}
+
+/// Result of "getdeploymentinfo".
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct GetDeploymentInfoResult {
+    /// The block the deployment states are evaluated at.
+    pub hash: BlockHash,
+    pub height: u32,
+    pub deployments: HashMap<String, DeploymentInfo>,
+}
+
+/// State of a soft fork deployment.
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct DeploymentInfo {
+    /// Either "buried" or "bip9".
+    #[serde(rename = "type")]
+    pub deployment_type: String,
+    /// Height of the first block the rules are enforced at, once active.
+    pub height: Option<u32>,
+    /// Whether the rules are enforced for the next block.
+    pub active: bool,
+    /// Present for "bip9" deployments.
+    pub bip9: Option<Bip9Info>,
+}
+
+/// BIP9 signalling state of a deployment.
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct Bip9Info {
+    /// Version bit used to signal, while "started" or "locked_in".
+    pub bit: Option<u8>,
+    pub start_time: i64,
+    pub timeout: i64,
+    pub min_activation_height: u32,
+    /// One of "defined", "started", "locked_in", "active" or "failed".
+    pub status: String,
+    /// Height of the first block this status applied to.
+    pub since: u32,
+    /// Status of the next block.
+    pub status_next: String,
+    /// Signalling statistics of the current period, while "started" or
+    /// "locked_in".
+    pub statistics: Option<Bip9Statistics>,
+    /// Signalling of each block of the period so far, '#' for signalling
+    /// blocks and '-' otherwise.
+    pub signalling: Option<String>,
+}
+
+/// Signalling statistics of the current BIP9 period.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct Bip9Statistics {
+    /// Length of the period in blocks.
+    pub period: u32,
+    /// Signalling blocks needed to lock in, while "started".
+    pub threshold: Option<u32>,
+    /// Blocks elapsed since the period started.
+    pub elapsed: u32,
+    /// Signalling blocks in the period so far.
+    pub count: u32,
+    /// Whether the threshold can still be reached, while "started".
+    pub possible: Option<bool>,
+}
+
+/// Result of "getchainstates".
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct GetChainStatesResult {
+    /// Number of headers seen.
+    pub headers: u64,
+    /// The chainstates, ordered with the most-work one last.
+    pub chainstates: Vec<ChainState>,
+}
+
+/// A chainstate, either validated from genesis or loaded from a UTXO
+/// snapshot.
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct ChainState {
+    pub blocks: u64,
+    #[serde(rename = "bestblockhash")]
+    pub best_block_hash: BlockHash,
+    pub difficulty: f64,
+    #[serde(rename = "verificationprogress")]
+    pub verification_progress: f64,
+    /// The base block of the snapshot this chainstate was loaded from.
+    pub snapshot_blockhash: Option<BlockHash>,
+    pub coins_db_cache_bytes: u64,
+    pub coins_tip_cache_bytes: u64,
+    /// Whether the chainstate is fully validated, false for a snapshot
+    /// chainstate still being checked in the background.
+    pub validated: bool,
+}
+
+/// A transaction to include with "generateblock".
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum GenerateBlockTx {
+    /// A transaction from the mempool.
+    Mempool(Txid),
+    /// A transaction that need not be in the mempool.
+    Raw(Transaction),
+}
+
+impl Serialize for GenerateBlockTx {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            GenerateBlockTx::Mempool(txid) => txid.serialize(serializer),
+            GenerateBlockTx::Raw(tx) => serializer.serialize_str(&encode::serialize_hex(tx)),
+        }
+    }
+}
+
+/// Result of "generateblock".
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct GenerateBlockResult {
+    pub hash: BlockHash,
+    /// The serialized block, when it was not submitted.
+    pub hex: Option<String>,
+}
+
+/// Result of "dumptxoutset".
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct DumpTxOutSetResult {
+    pub coins_written: u64,
+    /// The block the snapshot is taken at.
+    pub base_hash: BlockHash,
+    pub base_height: u64,
+    /// Absolute path of the snapshot file.
+    pub path: String,
+    /// Hash of the UTXO set, as compared against the assumeutxo parameters.
+    pub txoutset_hash: String,
+    /// Number of transactions up to and including the base block.
+    pub nchaintx: u64,
+}
+
+/// Result of "loadtxoutset".
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct LoadTxOutSetResult {
+    pub coins_loaded: u64,
+    /// The base block of the snapshot, now the tip of the snapshot
+    /// chainstate.
+    pub tip_hash: BlockHash,
+    pub base_height: u64,
+    pub path: String,
+}
+
+/// Result of "getchaintxstats".
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct GetChainTxStatsResult {
+    /// Time of the final block of the window.
+    pub time: u64,
+    /// Number of transactions up to the final block of the window.
+    #[serde(rename = "txcount")]
+    pub tx_count: u64,
+    pub window_final_block_hash: BlockHash,
+    pub window_final_block_height: u64,
+    pub window_block_count: u64,
+    /// Number of transactions in the window, unless it is empty.
+    pub window_tx_count: Option<u64>,
+    /// Seconds elapsed over the window, unless it is empty.
+    pub window_interval: Option<u64>,
+    /// Transactions per second over the window, unless its interval is
+    /// zero.
+    #[serde(rename = "txrate")]
+    pub tx_rate: Option<f64>,
+}
+
+/// A block designated by hash or by height, as "getblockstats" accepts.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(untagged)]
+pub enum HashOrHeight {
+    Hash(BlockHash),
+    Height(u64),
+}
+
+impl From<BlockHash> for HashOrHeight {
+    fn from(hash: BlockHash) -> Self {
+        HashOrHeight::Hash(hash)
+    }
+}
+
+impl From<u64> for HashOrHeight {
+    fn from(height: u64) -> Self {
+        HashOrHeight::Height(height)
+    }
+}
@@ -0,0 +1,153 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use crate::{
+    client::{Client, Result},
+    error::Error,
+    relay::Relay,
+};
+
+/// RPC error code returned when the wallet must be unlocked first.
+pub(crate) const RPC_WALLET_UNLOCK_NEEDED: i64 = -13;
+
+/// Supplies the wallet passphrase when a call needs the wallet unlocked.
+///
+/// Implemented for closures returning the passphrase, or `None` to leave the
+/// wallet locked and let the call fail.
+pub trait SecretProvider: Send + Sync {
+    fn passphrase(&self) -> Option<String>;
+}
+
+impl<F: Fn() -> Option<String> + Send + Sync> SecretProvider for F {
+    fn passphrase(&self) -> Option<String> {
+        self()
+    }
+}
+
+/// How [Client] unlocks the wallet for calls failing with
+/// `RPC_WALLET_UNLOCK_NEEDED`, configured with [Client::auto_unlock].
+pub(crate) struct AutoUnlock {
+    pub(crate) provider: Box<dyn SecretProvider>,
+    pub(crate) timeout: Duration,
+}
+
+/// Returns true if `err` reports that the wallet must be unlocked.
+pub fn is_unlock_needed(err: &Error) -> bool {
+    matches!(err, Error::JsonRpcError(e) if e.code == RPC_WALLET_UNLOCK_NEEDED)
+}
+
+/// Returns `timeout` in whole seconds, as taken by `walletpassphrase`,
+/// rounded up so that a sub-second timeout still unlocks the wallet.
+pub(crate) fn timeout_secs(timeout: Duration) -> u64 {
+    timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0)
+}
+
+/// Locks the wallet from a spawned task if dropped before being disarmed,
+/// i.e. when the future unlocking the wallet panics or is cancelled.
+pub(crate) struct RelockOnDrop {
+    relay: Option<Relay>,
+}
+
+impl RelockOnDrop {
+    pub(crate) fn new(relay: &Relay) -> Self {
+        Self {
+            relay: Some(relay.clone()),
+        }
+    }
+
+    /// Leaves the wallet as is on drop, once it has been locked in place.
+    pub(crate) fn disarm(mut self) {
+        self.relay = None;
+    }
+}
+
+impl Drop for RelockOnDrop {
+    fn drop(&mut self) {
+        let Some(relay) = self.relay.take() else {
+            return;
+        };
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            handle.spawn(async move {
+                let _ = relay.request::<_, ()>("walletlock", ()).await;
+            });
+        }
+    }
+}
+
+/// Keeps the wallet unlocked while alive.
+///
+/// The wallet is unlocked for at most `timeout`, after which the node locks
+/// it again by itself. Dropping the guard locks it right away from a spawned
+/// task; call [UnlockGuard::lock] to wait for the wallet to be locked and see
+/// the outcome.
+pub struct UnlockGuard {
+    client: Arc<Client>,
+    locked: bool,
+}
+
+impl UnlockGuard {
+    /// Unlocks the wallet for at most `timeout`.
+    pub async fn unlock(client: Arc<Client>, passphrase: &str, timeout: Duration) -> Result<Self> {
+        client.wallet_passphrase(passphrase, timeout).await?;
+        Ok(Self {
+            client,
+            locked: false,
+        })
+    }
+
+    /// Locks the wallet.
+    pub async fn lock(mut self) -> Result<()> {
+        self.locked = true;
+        self.client.wallet_lock().await
+    }
+}
+
+impl Drop for UnlockGuard {
+    fn drop(&mut self) {
+        if self.locked {
+            return;
+        }
+        let client = self.client.clone();
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            handle.spawn(async move {
+                let _ = client.wallet_lock().await;
+            });
+        }
+    }
+}
+
+/// Runs `f` with the wallet unlocked for at most `timeout`, and locks it
+/// again whether `f` succeeds, fails, panics or is cancelled.
+///
+/// The error of `f` takes precedence over an error locking the wallet.
+pub async fn with_unlocked<T, F, Fut>(
+    client: &Client,
+    passphrase: &str,
+    timeout: Duration,
+    f: F,
+) -> Result<T>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    client.wallet_passphrase(passphrase, timeout).await?;
+    let relock = RelockOnDrop::new(client.relay());
+    let res = f().await;
+    let locked = client.wallet_lock().await;
+    relock.disarm();
+    let value = res?;
+    locked?;
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounds_timeouts_up_to_seconds() {
+        assert_eq!(timeout_secs(Duration::ZERO), 0);
+        assert_eq!(timeout_secs(Duration::from_millis(1)), 1);
+        assert_eq!(timeout_secs(Duration::from_secs(60)), 60);
+        assert_eq!(timeout_secs(Duration::from_millis(60_500)), 61);
+    }
+}